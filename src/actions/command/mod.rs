@@ -0,0 +1,710 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::future;
+use futures::prelude::*;
+use humantime;
+#[cfg(unix)]
+use libc;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+use tokio_process::{Child, CommandExt as AsyncCommandExt};
+
+use multi::Multi;
+use paths::expand_home;
+use self::output::{Output, Target};
+use self::stop::Stop;
+use self::supervise::{wait, Policy, Restart, Supervisor};
+use super::{Action, Event};
+
+mod output;
+mod stop;
+mod supervise;
+mod words;
+
+pub const ACTION_NAME: &'static str = "command";
+
+/// The time leave commands may take by default, in seconds.
+const DEFAULT_LEAVE_TIMEOUT: u64 = 60;
+
+/// An action that executes a command on context enter.
+///
+/// Commands given as a single line are split into words like a shell
+/// would do it (see `words::split`), but not run through one unless
+/// `shell: true` is set. Commands can also be given as a list of
+/// arguments, which are passed on verbatim.
+///
+/// Commands learn why they were run from the `RUNTEXT_CONTEXT`,
+/// `RUNTEXT_EVENT` (`enter` or `leave`) and `RUNTEXT_TRIGGER` environment
+/// variables.
+///
+/// The output of commands is discarded, unless it is captured to the
+/// daemon's log and/or a log file per context using `output: [log, file]`.
+///
+/// With `mode: oneshot`, entering the context completes once the enter
+/// command has exited, failing if it exits with a non-zero status or runs
+/// longer than `enter_timeout`.
+///
+/// With `mode: daemon`, the default, the enter command is a long-running
+/// process that is stopped when the context is left or the `CommandAction`
+/// is dropped, by sending `stop_signal` (`SIGTERM` by default) to its
+/// process group and killing it after `stop_timeout`. While the context
+/// is active, it can be restarted when it exits using `restart: on-failure`
+/// or `restart: always`, and it is stopped once it runs longer than
+/// `enter_timeout`.
+///
+/// Leaving the context completes once the leave command has exited, it
+/// is stopped if it takes longer than `leave_timeout` (a minute by
+/// default). Failing leave commands are reported as errors.
+#[derive(Debug)]
+pub struct CommandAction {
+    child: Option<Supervisor>,
+    enter_command: Vec<String>,
+    exit_command: Option<Vec<String>>,
+    mode: Mode,
+    options: Options,
+    policy: Policy,
+    stop: Stop,
+    leave_timeout: Duration,
+    handle: Handle,
+}
+
+/// Whether the enter command runs while the context is active, or is
+/// just a step of entering it.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Mode {
+    /// The command is run to completion when the context is entered.
+    Oneshot,
+
+    /// The command runs until the context is left.
+    Daemon,
+}
+
+/// The environment commands are run in.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Options {
+    env: BTreeMap<String, String>,
+    env_clear: bool,
+    cwd: Option<PathBuf>,
+    umask: Option<u32>,
+    output: Output,
+}
+
+impl Options {
+    /// Builds the command for the given arguments, run for the given
+    /// kind of event (`enter` or `leave`).
+    fn command(&self, argv: &[String], kind: &str, event: &Event) -> Command {
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..])
+            .stdin(Stdio::null())
+            .stdout(self.output.stdio())
+            .stderr(self.output.stdio());
+
+        if self.env_clear {
+            command.env_clear();
+        }
+        command.envs(&self.env)
+            .env("RUNTEXT_CONTEXT", &event.context)
+            .env("RUNTEXT_EVENT", kind)
+            .env("RUNTEXT_TRIGGER", &event.trigger);
+        if let Some(ref cwd) = self.cwd {
+            command.current_dir(cwd);
+        }
+        self.set_umask(&mut command);
+
+        command
+    }
+
+    #[cfg(unix)]
+    fn set_umask(&self, command: &mut Command) {
+        if let Some(mask) = self.umask {
+            // umask is async-signal-safe and thus fine to call after fork.
+            unsafe {
+                command.pre_exec(move || {
+                    libc::umask(mask as libc::mode_t);
+                    Ok(())
+                });
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    fn set_umask(&self, _: &mut Command) {}
+
+    /// Spawns the command for the given arguments in its own process
+    /// group and starts capturing its output.
+    fn spawn(&self, argv: &[String], kind: &str, event: &Event, stop: &Stop, handle: &Handle) -> io::Result<Child> {
+        let mut command = self.command(argv, kind, event);
+        stop.prepare(&mut command);
+
+        let mut child = command.spawn_async_with_handle(handle.new_tokio_handle())?;
+        self.output.capture(&mut child, &event.context, handle)?;
+
+        Ok(child)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CommandConfig {
+    enter: Option<CommandLine>,
+    argv: Option<Vec<String>>,
+    leave: Option<CommandLine>,
+    #[serde(default)]
+    shell: bool,
+    mode: Option<Mode>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    env_clear: bool,
+    cwd: Option<String>,
+    umask: Option<Umask>,
+    output: Option<Multi<Target>>,
+    log_file: Option<String>,
+    log_size: Option<u64>,
+    log_keep: Option<usize>,
+    restart: Option<Restart>,
+    max_restarts: Option<u32>,
+    restart_delay: Option<String>,
+    max_restart_delay: Option<String>,
+    stop_signal: Option<String>,
+    stop_timeout: Option<String>,
+    enter_timeout: Option<String>,
+    leave_timeout: Option<String>,
+}
+
+/// A file mode creation mask, given as a quoted string of octal digits
+/// like `'027'`.
+///
+/// Unquoted masks are rejected, YAML reads `027` as decimal and `0o027`
+/// as octal, so there is no telling which digits were meant.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Umask {
+    Number(i64),
+    Octal(String),
+}
+
+impl Umask {
+    fn into_mode(self) -> io::Result<u32> {
+        let digits = match self {
+            Umask::Number(n) => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid umask {}, it has to be quoted like '027'.", n),
+            )),
+            Umask::Octal(s) => s,
+        };
+
+        u32::from_str_radix(digits.trim(), 8)
+            .ok()
+            .filter(|&mode| mode <= 0o777)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid umask '{}'.", digits)))
+    }
+}
+
+/// A command as it is written in the configuration.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CommandLine {
+    Line(String),
+    Argv(Vec<String>),
+}
+
+impl CommandLine {
+    /// Turns the command into the list of its arguments, the first one
+    /// being the program to run.
+    fn into_argv(self, shell: bool) -> io::Result<Vec<String>> {
+        let argv = match self {
+            CommandLine::Line(line) => if shell {
+                shell_argv(line)
+            } else {
+                words::split(&line)?
+            },
+            CommandLine::Argv(_) if shell => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Commands given as argument lists can't be run through the shell.",
+            )),
+            CommandLine::Argv(argv) => argv,
+        };
+
+        if argv.first().map_or(true, |program| program.is_empty()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing command name."));
+        }
+
+        Ok(argv)
+    }
+}
+
+impl CommandAction {
+    /// Creates a new `CommandAction` from the given command lines, which
+    /// are split into words.
+    pub fn new(enter_command: &str, exit_command: Option<&str>, handle: Handle) -> io::Result<Self> {
+        let exit_command = match exit_command {
+            Some(line) => Some(CommandLine::Line(line.to_owned()).into_argv(false)?),
+            None => None,
+        };
+
+        Ok(CommandAction {
+            child: None,
+            enter_command: CommandLine::Line(enter_command.to_owned()).into_argv(false)?,
+            exit_command,
+            mode: Mode::Daemon,
+            options: Options::default(),
+            policy: Policy::default(),
+            stop: Stop::default(),
+            leave_timeout: Duration::from_secs(DEFAULT_LEAVE_TIMEOUT),
+            handle,
+        })
+    }
+
+    pub fn from_config(value: &Value, handle: Handle) -> io::Result<Self> {
+        if let Value::String(ref cmd) = *value {
+            return Self::new(cmd, None, handle);
+        }
+
+        let cfg: CommandConfig = match *value {
+            Value::Sequence(_) => CommandConfig {
+                argv: Some(Self::parse(value)?),
+                ..CommandConfig::default()
+            },
+            Value::Mapping(_) => Self::parse(value)?,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown configuration format")),
+        };
+
+        let enter = match (cfg.enter, cfg.argv) {
+            (Some(enter), None) => enter,
+            (None, Some(argv)) => CommandLine::Argv(argv),
+            (Some(_), Some(_)) => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Only one of enter and argv may be given.",
+            )),
+            (None, None) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing enter command key.")),
+        };
+        let exit_command = match cfg.leave {
+            Some(leave) => Some(leave.into_argv(cfg.shell)?),
+            None => None,
+        };
+
+        let umask = match cfg.umask {
+            Some(umask) => Some(umask.into_mode()?),
+            None => None,
+        };
+
+        let mut output = Output::new(cfg.output.into_iter().flatten());
+        if let Some(ref path) = cfg.log_file {
+            output.path(path);
+        }
+        if let Some(size) = cfg.log_size {
+            output.max_size(size);
+        }
+        if let Some(keep) = cfg.log_keep {
+            output.keep(keep);
+        }
+
+        let mode = cfg.mode.unwrap_or(Mode::Daemon);
+        let restarts = cfg.restart.is_some() || cfg.max_restarts.is_some() ||
+            cfg.restart_delay.is_some() || cfg.max_restart_delay.is_some();
+        if mode == Mode::Oneshot && restarts {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "One-shot commands can't be restarted, use mode: daemon.",
+            ));
+        }
+
+        let mut policy = Policy::default();
+        if let Some(restart) = cfg.restart {
+            policy.restart = restart;
+        }
+        policy.max_restarts = cfg.max_restarts;
+        if let Some(ref delay) = cfg.restart_delay {
+            policy.delay = parse_duration(delay)?;
+        }
+        if let Some(ref delay) = cfg.max_restart_delay {
+            policy.max_delay = parse_duration(delay)?;
+        }
+        if let Some(ref timeout) = cfg.enter_timeout {
+            policy.timeout = Some(parse_duration(timeout)?);
+        }
+        let leave_timeout = match cfg.leave_timeout {
+            Some(ref timeout) => parse_duration(timeout)?,
+            None => Duration::from_secs(DEFAULT_LEAVE_TIMEOUT),
+        };
+
+        let mut stop = Stop::default();
+        if let Some(ref signal) = cfg.stop_signal {
+            stop.signal(signal)?;
+        }
+        if let Some(ref timeout) = cfg.stop_timeout {
+            stop.timeout(parse_duration(timeout)?);
+        }
+
+        Ok(CommandAction {
+            child: None,
+            enter_command: enter.into_argv(cfg.shell)?,
+            exit_command,
+            mode,
+            options: Options {
+                env: cfg.env,
+                env_clear: cfg.env_clear,
+                cwd: cfg.cwd.map(|cwd| expand_home(&cwd)),
+                umask,
+                output,
+            },
+            policy,
+            stop,
+            leave_timeout,
+            handle,
+        })
+    }
+
+    fn parse<T>(value: &Value) -> io::Result<T>
+        where T: for<'de> ::serde::Deserialize<'de> {
+        serde_yaml::from_value(value.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid command configuration: {}", e)))
+    }
+
+    fn enter_impl(&mut self, event: &Event) -> io::Result<()> {
+        let argv = self.enter_command.clone();
+        let options = self.options.clone();
+        let ev = event.clone();
+        let stop = self.stop.clone();
+        let handle = self.handle.clone();
+        let spawn = move || options.spawn(&argv, "enter", &ev, &stop, &handle);
+
+        let name = format!("[{}] `{}`", event.context, self.enter_command[0]);
+        self.child = Some(Supervisor::start(spawn, self.policy.clone(), self.stop.clone(), name, &self.handle)?);
+
+        Ok(())
+    }
+
+    /// Runs the given command to completion, for the given kind of event.
+    fn run(
+        argv: &[String],
+        kind: &str,
+        event: &Event,
+        options: &Options,
+        stop: &Stop,
+        timeout: Option<Duration>,
+        handle: &Handle,
+    ) -> Box<Future<Item = (), Error = io::Error>> {
+        let child = match options.spawn(argv, kind, event, stop, handle) {
+            Ok(child) => child,
+            Err(err) => return Box::new(future::err(err)),
+        };
+
+        let name = format!("[{}] `{}`", event.context, argv[0]);
+        let fut = wait(Rc::new(RefCell::new(Some(child))), timeout, stop.clone(), handle)
+            .and_then(move |exit| match exit {
+                Some(exit) if !exit.success() => Err(io::Error::new(io::ErrorKind::Other, format!("{} {}.", name, exit))),
+                _ => Ok(()),
+            });
+        Box::new(fut)
+    }
+}
+
+fn parse_duration(duration: &str) -> io::Result<Duration> {
+    humantime::parse_duration(duration)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid duration '{}': {}", duration, e)))
+}
+
+#[cfg(unix)]
+fn shell_argv(line: String) -> Vec<String> {
+    vec!["/bin/sh".to_owned(), "-c".to_owned(), line]
+}
+
+#[cfg(windows)]
+fn shell_argv(line: String) -> Vec<String> {
+    vec!["cmd".to_owned(), "/C".to_owned(), line]
+}
+
+impl Action for CommandAction {
+    fn enter(&mut self, event: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        match self.mode {
+            Mode::Oneshot => Self::run(
+                &self.enter_command,
+                "enter",
+                event,
+                &self.options,
+                &self.stop,
+                self.policy.timeout,
+                &self.handle,
+            ),
+            Mode::Daemon => Box::new(future::result(self.enter_impl(event))),
+        }
+    }
+
+    fn leave(&mut self, event: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        let stopped = match self.child.take().and_then(Supervisor::stop) {
+            Some(child) => self.stop.stop(child, &self.handle),
+            None => Box::new(future::ok(())),
+        };
+
+        let argv = match self.exit_command {
+            Some(ref argv) => argv.clone(),
+            None => return stopped,
+        };
+        let event = event.clone();
+        let options = self.options.clone();
+        let stop = self.stop.clone();
+        let timeout = Some(self.leave_timeout);
+        let handle = self.handle.clone();
+
+        let fut = stopped.and_then(move |_| Self::run(&argv, "leave", &event, &options, &stop, timeout, &handle));
+        Box::new(fut)
+    }
+}
+
+impl Drop for CommandAction {
+    fn drop(&mut self) {
+        if let Some(child) = self.child.take().and_then(Supervisor::stop) {
+            self.stop.stop_detached(child, &self.handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use serde_yaml::Mapping;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn smoke() {
+        execute_and_kill("true")
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn smoke() {
+        execute_and_kill("cmd /C exit 0")
+    }
+
+    #[should_panic]
+    #[test]
+    fn smoke_fail() {
+        execute_and_kill("this-is-a-nonexisting-process")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn starting_space() {
+        execute_and_kill(" true");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn starting_space() {
+        execute_and_kill(" cmd /C exit 0");
+    }
+
+    fn execute_and_kill(cmd: &str) {
+        let mut core = Core::new().unwrap();
+        let mut action = CommandAction::new(cmd, None, core.handle()).unwrap();
+
+        action.enter(&event()).wait().unwrap();
+        core.run(action.leave(&event())).unwrap();
+    }
+
+    /// Gets a handle for actions that are never executed.
+    fn handle() -> Handle {
+        Core::new().unwrap().handle()
+    }
+
+    fn event() -> Event {
+        Event {
+            context: "Test".to_owned(),
+            trigger: "wifi".to_owned(),
+        }
+    }
+
+    #[test]
+    fn load_cfg() {
+        let cfg = Value::String("enter".to_owned());
+        CommandAction::from_config(&cfg, handle()).unwrap();
+
+        let enter_k = Value::String("enter".to_owned());
+        let leave_k = Value::String("leave".to_owned());
+        let mut map = Mapping::new();
+        map.insert(enter_k, Value::String("enter".to_owned()));
+        map.insert(leave_k, Value::String("enter".to_owned()));
+        let cfg2 = Value::Mapping(map);
+        CommandAction::from_config(&cfg2, handle()).unwrap();
+
+        let cfg = serde_yaml::from_str("{ enter: touch /tmp/a, mode: oneshot }").unwrap();
+        let action = CommandAction::from_config(&cfg, handle()).unwrap();
+        assert_eq!(action.mode, Mode::Oneshot);
+
+        // Oneshot commands are not supervised, so they can't be restarted.
+        let cfg = serde_yaml::from_str("{ enter: touch /tmp/a, mode: oneshot, restart: always }").unwrap();
+        assert!(CommandAction::from_config(&cfg, handle()).is_err());
+    }
+
+    #[test]
+    fn load_cfg_map_empty_leave() {
+        let cfg = Value::String("enter".to_owned());
+        CommandAction::from_config(&cfg, handle()).unwrap();
+
+        let enter_k = Value::String("enter".to_owned());
+        let mut map = Mapping::new();
+        map.insert(enter_k, Value::String("enter".to_owned()));
+        let cfg2 = Value::Mapping(map);
+        CommandAction::from_config(&cfg2, handle()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail1() {
+        let cfg = Value::String("".to_owned());
+        CommandAction::from_config(&cfg, handle()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail2() {
+        let enter_k = Value::String("enter".to_owned());
+        let leave_k = Value::String("leave".to_owned());
+        let mut map = Mapping::new();
+        map.insert(enter_k, Value::String("".to_owned()));
+        map.insert(leave_k, Value::String("enter".to_owned()));
+        let cfg2 = Value::Mapping(map);
+        CommandAction::from_config(&cfg2, handle()).unwrap();
+    }
+
+    #[test]
+    fn load_cfg_forms() {
+        let action = CommandAction::new("touch \"/tmp/my file\"", None, handle()).unwrap();
+        assert_eq!(action.enter_command, vec!["touch", "/tmp/my file"]);
+
+        let cfg = serde_yaml::from_str("[touch, /tmp/my file]").unwrap();
+        let action = CommandAction::from_config(&cfg, handle()).unwrap();
+        assert_eq!(action.enter_command, vec!["touch", "/tmp/my file"]);
+
+        let cfg = serde_yaml::from_str("{ argv: [touch, /tmp/a], leave: [rm, /tmp/a] }").unwrap();
+        let action = CommandAction::from_config(&cfg, handle()).unwrap();
+        assert_eq!(action.exit_command, Some(vec!["rm".to_owned(), "/tmp/a".to_owned()]));
+
+        let cfg = serde_yaml::from_str("{ enter: 'echo $HOME > /tmp/a', shell: true }").unwrap();
+        let action = CommandAction::from_config(&cfg, handle()).unwrap();
+        assert_eq!(action.enter_command[2], "echo $HOME > /tmp/a");
+    }
+
+    #[test]
+    fn load_cfg_errors() {
+        let cfgs = [
+            "'touch \"/tmp/unterminated'",
+            "{ enter: '   ' }",
+            "{ argv: [] }",
+            "{ enter: touch, argv: [touch] }",
+            "{ enter: [touch, /tmp/a], shell: true }",
+            "{ enter: syncthing, restart: sometimes }",
+            "{ enter: syncthing, restart: always, restart_delay: soon }",
+            "{ enter: touch /tmp/a, mode: oneshot, restart: on-failure }",
+            "{ enter: touch /tmp/a, mode: sometimes }",
+            "{ enter: touch /tmp/a, umask: 027 }",
+            "{ enter: touch /tmp/a, umask: 0o027 }",
+            "{ enter: touch /tmp/a, umask: '089' }",
+        ];
+
+        for cfg in cfgs.iter() {
+            let cfg = serde_yaml::from_str(cfg).unwrap();
+            assert!(CommandAction::from_config(&cfg, handle()).is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn passes_environment() {
+
+        let out = env::temp_dir().join(format!("runtext-command-{}", process::id()));
+        let cfg = format!(
+            "{{ enter: 'true', leave: 'echo $RUNTEXT_CONTEXT $RUNTEXT_EVENT $RUNTEXT_TRIGGER $GREETING $(pwd) $(umask) > {}', \
+               shell: true, env: {{ GREETING: hello }}, cwd: /, umask: '027' }}",
+            out.display(),
+        );
+        let mut core = Core::new().unwrap();
+        let mut action = CommandAction::from_config(&serde_yaml::from_str(&cfg).unwrap(), core.handle()).unwrap();
+
+        action.enter(&event()).wait().unwrap();
+        core.run(action.leave(&event())).unwrap();
+
+        assert_eq!(fs::read_to_string(&out).unwrap(), "Test leave wifi hello / 0027\n");
+        fs::remove_file(out).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn captures_output() {
+
+        let log = env::temp_dir().join(format!("runtext-command-{}.log", process::id()));
+        let cfg = format!(
+            "{{ enter: 'echo out; echo err >&2', shell: true, output: file, log_file: {} }}",
+            log.display(),
+        );
+        let mut core = Core::new().unwrap();
+        let mut action = CommandAction::from_config(&serde_yaml::from_str(&cfg).unwrap(), core.handle()).unwrap();
+        action.enter(&event()).wait().unwrap();
+
+        let mut lines = Vec::new();
+        for _ in 0..100 {
+            core.turn(Some(Duration::from_millis(20)));
+            lines = fs::read_to_string(&log).unwrap().lines().map(|l| l.to_owned()).collect::<Vec<_>>();
+            if lines.len() == 2 {
+                break;
+            }
+        }
+        lines.sort();
+
+        assert_eq!(lines, vec!["err", "out"]);
+        fs::remove_file(log).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fails_on_failing_leave_commands() {
+        let mut core = Core::new().unwrap();
+        let cfgs = [
+            "{ enter: 'true', leave: 'false' }",
+            "{ enter: 'true', leave: 'sleep 10', leave_timeout: 50ms }",
+        ];
+
+        for cfg in cfgs.iter() {
+            let mut action = CommandAction::from_config(&serde_yaml::from_str(cfg).unwrap(), core.handle()).unwrap();
+
+            action.enter(&event()).wait().unwrap();
+            assert!(core.run(action.leave(&event())).is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn awaits_oneshot_commands() {
+        let mut core = Core::new().unwrap();
+
+        let cfg = serde_yaml::from_str("{ enter: 'sleep 0.1', mode: oneshot }").unwrap();
+        let mut action = CommandAction::from_config(&cfg, core.handle()).unwrap();
+        core.run(action.enter(&event())).unwrap();
+        assert!(action.child.is_none());
+
+        let cfgs = [
+            "{ enter: 'false', mode: oneshot }",
+            "{ enter: 'sleep 10', mode: oneshot, enter_timeout: 50ms }",
+        ];
+        for cfg in cfgs.iter() {
+            let mut action = CommandAction::from_config(&serde_yaml::from_str(cfg).unwrap(), core.handle()).unwrap();
+            assert!(core.run(action.enter(&event())).is_err());
+        }
+    }
+}
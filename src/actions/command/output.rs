@@ -0,0 +1,330 @@
+//! Capturing the output of commands, which is otherwise discarded.
+//!
+//! Output can be written to the daemon's log, prefixed with the name of
+//! the context, and to a log file per context, which is rotated once it
+//! grows too large.
+
+use std::cell::RefCell;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::rc::Rc;
+
+use futures::prelude::*;
+use futures::stream;
+use tokio_core::reactor::Handle;
+use tokio_io::AsyncRead;
+use tokio_process::Child;
+
+use paths::expand_home;
+
+/// The default size in bytes a log file may grow to before it is rotated.
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// The default number of rotated log files to keep.
+const DEFAULT_KEEP: usize = 3;
+
+/// Where the output of a command goes.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    /// The output is thrown away. This is the default.
+    Discard,
+
+    /// The output is written to the daemon's log.
+    Log,
+
+    /// The output is written to the context's log file.
+    File,
+}
+
+/// Configures how the output of commands is captured.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Output {
+    log: bool,
+    file: bool,
+    path: Option<PathBuf>,
+    max_size: u64,
+    keep: usize,
+}
+
+impl Output {
+    /// Creates a new `Output` writing to the given targets.
+    pub fn new<I: IntoIterator<Item = Target>>(targets: I) -> Self {
+        let targets = targets.into_iter().collect::<Vec<_>>();
+
+        Output {
+            log: targets.contains(&Target::Log),
+            file: targets.contains(&Target::File),
+            path: None,
+            max_size: DEFAULT_MAX_SIZE,
+            keep: DEFAULT_KEEP,
+        }
+    }
+
+    /// Sets the log file, instead of one named after the context in the
+    /// state directory.
+    pub fn path(&mut self, path: &str) -> &mut Self {
+        self.path = Some(expand_home(path));
+        self
+    }
+
+    /// Sets the size in bytes the log file may grow to before it is rotated.
+    pub fn max_size(&mut self, max_size: u64) -> &mut Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets the number of rotated log files to keep.
+    pub fn keep(&mut self, keep: usize) -> &mut Self {
+        self.keep = keep;
+        self
+    }
+
+    /// Checks whether any output is captured at all.
+    pub fn is_captured(&self) -> bool {
+        self.log || self.file
+    }
+
+    /// Gets the way the output streams of commands need to be set up.
+    pub fn stdio(&self) -> Stdio {
+        if self.is_captured() { Stdio::piped() } else { Stdio::null() }
+    }
+
+    /// Creates a writer for the output of a command run for the given context.
+    pub fn writer(&self, context: &str) -> io::Result<Writer> {
+        let file = if self.file {
+            let path = self.path.clone().unwrap_or_else(|| default_path(context));
+            Some(LogFile::open(path, self.max_size, self.keep)?)
+        } else {
+            None
+        };
+
+        Ok(Writer {
+            context: context.to_owned(),
+            log: self.log,
+            file,
+        })
+    }
+
+    /// Starts forwarding the output of the given child, spawned with
+    /// `stdio()` as its stdout and stderr, in the background.
+    pub fn capture(&self, child: &mut Child, context: &str, handle: &Handle) -> io::Result<()> {
+        if !self.is_captured() {
+            return Ok(());
+        }
+
+        let writer = self.writer(context)?;
+        let stdout = child.stdout().take()
+            .map(|out| Box::new(LossyLines::new(out)) as Box<Stream<Item = String, Error = io::Error>>)
+            .unwrap_or_else(|| Box::new(stream::empty()));
+        let stderr = child.stderr().take()
+            .map(|err| Box::new(LossyLines::new(err)) as Box<Stream<Item = String, Error = io::Error>>)
+            .unwrap_or_else(|| Box::new(stream::empty()));
+
+        let writer = Rc::new(RefCell::new(writer));
+        let forward = stdout.select(stderr)
+            .for_each(move |line| {
+                writer.borrow_mut().write_line(&line);
+                Ok(())
+            })
+            .map_err(|err| eprintln!("Failed to read command output: {}.", err));
+        handle.spawn(forward);
+
+        Ok(())
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output::new(None)
+    }
+}
+
+/// Writes the output of a single command.
+#[derive(Debug)]
+pub struct Writer {
+    context: String,
+    log: bool,
+    file: Option<LogFile>,
+}
+
+impl Writer {
+    /// Writes a single line of output.
+    ///
+    /// Failures are logged rather than returned, the command must not
+    /// notice that its output can't be written.
+    pub fn write_line(&mut self, line: &str) {
+        if self.log {
+            eprintln!("[{}] {}", self.context, line);
+        }
+
+        let failed = match self.file {
+            Some(ref mut file) => file.write_line(line).err(),
+            None => None,
+        };
+        if let Some(err) = failed {
+            eprintln!("Failed to write to log file of context '{}': {}.", self.context, err);
+        }
+    }
+}
+
+/// A log file that is rotated once it grows too large.
+///
+/// Rotated files get a numeric suffix, the newest one being `.1`.
+#[derive(Debug)]
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_size: u64, keep: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(LogFile { path, file, size, max_size, keep })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 + 1 > self.max_size {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..self.keep).rev() {
+                match fs::rename(rotated(&self.path, i), rotated(&self.path, i + 1)) {
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+                    res => res?,
+                }
+            }
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+/// A stream of the lines read from a pipe.
+///
+/// Unlike `tokio_io::io::Lines` it doesn't fail on invalid UTF-8, which
+/// commands print every now and then.
+#[derive(Debug)]
+struct LossyLines<R> {
+    reader: BufReader<R>,
+    line: Vec<u8>,
+}
+
+impl<R: AsyncRead> LossyLines<R> {
+    fn new(reader: R) -> Self {
+        LossyLines {
+            reader: BufReader::new(reader),
+            line: Vec::new(),
+        }
+    }
+}
+
+impl<R: AsyncRead> Stream for LossyLines<R> {
+    type Item = String;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // Partially read lines stay in the buffer until the rest arrives.
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(0) if self.line.is_empty() => return Ok(Async::Ready(None)),
+            Ok(_) => {},
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+            Err(e) => return Err(e),
+        }
+
+        while self.line.last() == Some(&b'\n') || self.line.last() == Some(&b'\r') {
+            self.line.pop();
+        }
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+
+        Ok(Async::Ready(Some(line)))
+    }
+}
+
+/// Gets the log file of the given context, in `$XDG_STATE_HOME/runtext`.
+fn default_path(context: &str) -> PathBuf {
+    let dir = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => expand_home("~/.local/state"),
+    };
+
+    // Context names are free text and may contain path separators.
+    let name = context.replace(&['/', '\\'][..], "_");
+    dir.join("runtext").join(format!("{}.log", name))
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(format!(".{}", index));
+
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn rotates_log_files() {
+        let dir = env::temp_dir().join(format!("runtext-output-{}", process::id()));
+        let path = dir.join("Work.log");
+
+        let mut output = Output::new(vec![Target::File]);
+        output.path(path.to_str().unwrap())
+            .max_size(8)
+            .keep(2);
+        let mut writer = output.writer("Work").unwrap();
+        for line in &["one", "two", "three", "four"] {
+            writer.write_line(line);
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "four\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 1)).unwrap(), "three\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 2)).unwrap(), "one\ntwo\n");
+        assert!(!rotated(&path, 3).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_lossy_lines() {
+        let input: &[u8] = b"caf\xc3\xa9\r\n\xff\nlast";
+        let lines = LossyLines::new(input).collect().wait().unwrap();
+
+        assert_eq!(lines, vec!["café", "\u{fffd}", "last"]);
+    }
+
+    #[test]
+    fn default_paths() {
+        assert!(default_path("Home/Office").ends_with("runtext/Home_Office.log"));
+    }
+}
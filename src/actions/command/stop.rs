@@ -0,0 +1,240 @@
+//! Stopping commands gracefully.
+//!
+//! On Unix, every command runs in its own process group, so that
+//! processes started by shell wrappers are stopped along with it. The
+//! group is sent a configurable signal first and killed if it is still
+//! around after a grace period.
+
+use std::io;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::Duration;
+
+use futures::future;
+use futures::prelude::*;
+#[cfg(unix)]
+use libc::{self, c_int, pid_t};
+use tokio_core::reactor::Handle;
+#[cfg(unix)]
+use tokio_core::reactor::{Interval, Timeout};
+use tokio_process::Child;
+
+/// The signals commands may be stopped with.
+#[cfg(unix)]
+const SIGNALS: &'static [(&'static str, c_int)] = &[
+    ("TERM", libc::SIGTERM),
+    ("INT", libc::SIGINT),
+    ("HUP", libc::SIGHUP),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+];
+
+/// How often a stopped process group is checked for remaining processes,
+/// in milliseconds.
+#[cfg(unix)]
+const GROUP_POLL_INTERVAL: u64 = 50;
+
+/// Configures how commands are stopped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Stop {
+    #[cfg(unix)]
+    signal: c_int,
+    timeout: Duration,
+}
+
+impl Stop {
+    /// Sets the signal commands are asked to stop with, like `SIGTERM`
+    /// or `int`.
+    #[cfg(unix)]
+    pub fn signal(&mut self, name: &str) -> io::Result<&mut Self> {
+        let upper = name.trim().to_uppercase();
+        let short = if upper.starts_with("SIG") { &upper[3..] } else { &upper[..] };
+
+        self.signal = SIGNALS.iter()
+            .find(|&&(n, _)| n == short)
+            .map(|&(_, signal)| signal)
+            .ok_or(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported stop signal '{}'.", name),
+            ))?;
+        Ok(self)
+    }
+
+    #[cfg(windows)]
+    pub fn signal(&mut self, _: &str) -> io::Result<&mut Self> {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "Stop signals are not supported on this platform."))
+    }
+
+    /// Sets the time commands are given to exit before they are killed.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Makes the command start its own process group.
+    #[cfg(unix)]
+    pub fn prepare(&self, command: &mut Command) {
+        // setpgid is async-signal-safe and thus fine to call after fork.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setpgid(0, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(windows)]
+    pub fn prepare(&self, _: &mut Command) {}
+
+    /// Stops the given child and all processes in its group.
+    ///
+    /// The signal is sent right away, the returned future resolves once
+    /// all processes have exited or were killed after the grace period.
+    #[cfg(unix)]
+    pub fn stop(&self, child: Child, handle: &Handle) -> Box<Future<Item = (), Error = io::Error>> {
+        let group = child.id() as pid_t;
+        if let Err(err) = signal_group(group, self.signal) {
+            return Box::new(future::err(err));
+        }
+
+        let timeout = match Timeout::new(self.timeout, handle) {
+            Ok(timeout) => timeout,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        // Processes started by the command may exit after it, so the group
+        // is polled until it's empty. The child is polled along with it to
+        // reap it, it would stay in the group as a zombie otherwise.
+        let mut child = Some(child);
+        let exited = future::result(Interval::new(Duration::from_millis(GROUP_POLL_INTERVAL), handle))
+            .flatten_stream()
+            .take_while(move |_| {
+                let reaped = match child {
+                    Some(ref mut child) => child.poll()?.is_ready(),
+                    None => true,
+                };
+                if reaped {
+                    child = None;
+                }
+
+                Ok(!reaped || is_group_alive(group))
+            })
+            .for_each(|_| Ok(()));
+
+        // Kill the group if it doesn't exit in time. Killed processes may
+        // linger as zombies until they are reaped by init, so there's no
+        // point in waiting for them.
+        let killed = timeout.and_then(move |_| signal_group(group, libc::SIGKILL));
+        let fut = exited.select(killed)
+            .map(|_| ())
+            .map_err(|(err, _)| err);
+        Box::new(fut)
+    }
+
+    #[cfg(windows)]
+    pub fn stop(&self, mut child: Child, _: &Handle) -> Box<Future<Item = (), Error = io::Error>> {
+        Box::new(future::result(child.kill()))
+    }
+
+    /// Stops the given child in the background, e.g. when the action is
+    /// dropped and can't wait for it.
+    pub fn stop_detached(&self, child: Child, handle: &Handle) {
+        let fut = self.stop(child, handle)
+            .map_err(|err| eprintln!("Failed to stop command: {}.", err));
+        handle.spawn(fut);
+    }
+}
+
+impl Default for Stop {
+    fn default() -> Self {
+        Stop {
+            #[cfg(unix)]
+            signal: libc::SIGTERM,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Sends a signal to all processes in the given group.
+///
+/// A group without any processes left is not an error.
+#[cfg(unix)]
+fn signal_group(group: pid_t, signal: c_int) -> io::Result<()> {
+    if unsafe { libc::kill(-group, signal) } < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_group_alive(group: pid_t) -> bool {
+    unsafe {
+        libc::kill(-group, 0) == 0 ||
+            io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Instant;
+
+    use tokio_core::reactor::Core;
+    use tokio_process::CommandExt;
+
+    use super::*;
+
+    fn spawn(script: &str, stop: &Stop, handle: &Handle) -> Child {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        stop.prepare(&mut command);
+
+        command.spawn_async_with_handle(handle.new_tokio_handle()).unwrap()
+    }
+
+    #[test]
+    fn stops_whole_group() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        // The shell doesn't forward the signal to the backgrounded sleep.
+        let stop = Stop::default();
+        let child = spawn("sleep 10 & wait", &stop, &handle);
+        let group = child.id() as pid_t;
+
+        let started = Instant::now();
+        core.run(stop.stop(child, &handle)).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!is_group_alive(group));
+    }
+
+    #[test]
+    fn kills_after_timeout() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let mut stop = Stop::default();
+        stop.signal("usr1").unwrap()
+            .timeout(Duration::from_millis(100));
+        let child = spawn("trap '' USR1; sleep 10", &stop, &handle);
+
+        core.turn(Some(Duration::from_millis(50)));
+        let started = Instant::now();
+        core.run(stop.stop(child, &handle)).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn parses_signals() {
+        let mut stop = Stop::default();
+        assert_eq!(stop.signal("SIGINT").unwrap().signal, libc::SIGINT);
+        assert!(stop.signal("SIGSEGV").is_err());
+    }
+}
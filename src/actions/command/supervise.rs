@@ -0,0 +1,331 @@
+//! Keeping long-running commands alive while their context is active.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Either, Loop};
+use futures::prelude::*;
+use futures::unsync::oneshot;
+use humantime;
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_process::Child;
+
+use super::stop::Stop;
+
+/// Commands running at least this long are considered healthy again,
+/// which resets the restart delay and count.
+const HEALTHY_AFTER: u64 = 60;
+
+/// When a command is restarted after it exited.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Restart {
+    /// The command is never restarted. This is the default.
+    Never,
+
+    /// The command is restarted if it failed, i.e. exited with a non-zero
+    /// status or was killed by a signal.
+    OnFailure,
+
+    /// The command is always restarted.
+    Always,
+}
+
+/// Configures how commands are restarted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Policy {
+    pub restart: Restart,
+
+    /// The number of restarts after which a crashing command is given up.
+    pub max_restarts: Option<u32>,
+
+    /// The delay before the first restart, doubled with every further one.
+    pub delay: Duration,
+
+    /// The upper bound of the restart delay.
+    pub max_delay: Duration,
+
+    /// The time after which a run of the command is stopped.
+    pub timeout: Option<Duration>,
+}
+
+impl Policy {
+    fn restarts_after(&self, exit: Exit) -> bool {
+        match self.restart {
+            Restart::Never => false,
+            Restart::OnFailure => !exit.success(),
+            Restart::Always => true,
+        }
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            restart: Restart::Never,
+            max_restarts: None,
+            delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            timeout: None,
+        }
+    }
+}
+
+/// How a run of a command ended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Exit {
+    /// The command exited by itself.
+    Status(ExitStatus),
+
+    /// The command was stopped because it ran longer than allowed.
+    TimedOut(Duration),
+}
+
+impl Exit {
+    /// Checks whether the command exited successfully.
+    pub fn success(&self) -> bool {
+        match *self {
+            Exit::Status(status) => status.success(),
+            Exit::TimedOut(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Exit::Status(status) => write!(f, "exited with {}", status),
+            Exit::TimedOut(timeout) => write!(f, "timed out after {}", humantime::format_duration(timeout)),
+        }
+    }
+}
+
+/// Runs a command in the background, restarting it according to a
+/// `Policy` until it is stopped.
+///
+/// Exits of the command are reported in the daemon's log.
+#[derive(Debug)]
+pub struct Supervisor {
+    child: Rc<RefCell<Option<Child>>>,
+    _stop: oneshot::Sender<()>,
+}
+
+#[derive(Debug)]
+struct State {
+    restarts: u32,
+    delay: Duration,
+    started: Instant,
+}
+
+impl Supervisor {
+    /// Starts the command using `spawn`, which is called again for every
+    /// restart.
+    ///
+    /// Only failures to start the command the first time are returned,
+    /// later ones are reported in the log and end the supervision.
+    pub fn start<F>(mut spawn: F, policy: Policy, stop: Stop, name: String, handle: &Handle) -> io::Result<Self>
+        where F: FnMut() -> io::Result<Child> + 'static {
+        let child = Rc::new(RefCell::new(Some(spawn()?)));
+        let spawn = Rc::new(RefCell::new(spawn));
+        let (stop_tx, stop_rx) = oneshot::channel();
+
+        let state = State {
+            restarts: 0,
+            delay: policy.delay,
+            started: Instant::now(),
+        };
+        let slot = child.clone();
+        let h = handle.clone();
+        let supervise = future::loop_fn(state, move |state| {
+            let slot = slot.clone();
+            let spawn = spawn.clone();
+            let policy = policy.clone();
+            let name = name.clone();
+            let h = h.clone();
+
+            wait(slot.clone(), policy.timeout, stop.clone(), &h).and_then(move |exit| {
+                let exit = match exit {
+                    Some(exit) => exit,
+                    None => return Box::new(future::ok(Loop::Break(()))) as Box<Future<Item = _, Error = _>>,
+                };
+
+                match next_attempt(state, exit, &policy, &name) {
+                    Some(state) => {
+                        let restart = future::result(Timeout::new(state.delay, &h))
+                            .flatten()
+                            .and_then(move |_| {
+                                *slot.borrow_mut() = Some((*spawn.borrow_mut())()?);
+
+                                Ok(Loop::Continue(State {
+                                    restarts: state.restarts + 1,
+                                    delay: cmp::min(state.delay * 2, policy.max_delay),
+                                    started: Instant::now(),
+                                }))
+                            });
+                        Box::new(restart)
+                    },
+                    None => Box::new(future::ok(Loop::Break(()))),
+                }
+            })
+        });
+
+        // The supervision ends once the supervisor is dropped.
+        let supervise = supervise
+            .map_err(|err| eprintln!("Failed to restart command: {}.", err))
+            .select(stop_rx.then(|_| Ok(())))
+            .then(|_| Ok(()));
+        handle.spawn(supervise);
+
+        Ok(Supervisor {
+            child,
+            _stop: stop_tx,
+        })
+    }
+
+    /// Stops the supervision and hands out the command, unless it has
+    /// already exited.
+    pub fn stop(self) -> Option<Child> {
+        self.child.borrow_mut().take()
+    }
+}
+
+/// Decides whether the command is to be restarted after it exited,
+/// and if so, with which delay.
+fn next_attempt(mut state: State, exit: Exit, policy: &Policy, name: &str) -> Option<State> {
+    if !policy.restarts_after(exit) {
+        eprintln!("{} {}.", name, exit);
+        return None;
+    }
+
+    if state.started.elapsed() >= Duration::from_secs(HEALTHY_AFTER) {
+        state.restarts = 0;
+        state.delay = policy.delay;
+    }
+    if policy.max_restarts.map_or(false, |max| state.restarts >= max) {
+        eprintln!("{} {}, giving up after {} restarts.", name, exit, state.restarts);
+        return None;
+    }
+
+    eprintln!("{} {}, restarting in {}.", name, exit, humantime::format_duration(state.delay));
+    Some(state)
+}
+
+/// Waits for the child in the slot to exit and removes it, so it won't
+/// be killed later on.
+///
+/// If the child is still running after the timeout, it is stopped.
+/// Resolves to `None` if the child was taken out of the slot, i.e.
+/// the supervision was stopped.
+pub fn wait(slot: Rc<RefCell<Option<Child>>>, timeout: Option<Duration>, stop: Stop, handle: &Handle)
+    -> Box<Future<Item = Option<Exit>, Error = io::Error>> {
+    let s = slot.clone();
+    let exited = future::poll_fn(move || {
+        let mut slot = s.borrow_mut();
+        let status = match *slot {
+            Some(ref mut child) => try_ready!(child.poll()),
+            None => return Ok(Async::Ready(None)),
+        };
+
+        *slot = None;
+        Ok(Async::Ready(Some(Exit::Status(status))))
+    });
+
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Box::new(exited),
+    };
+    let timer = match Timeout::new(timeout, handle) {
+        Ok(timer) => timer,
+        Err(err) => return Box::new(future::err(err)),
+    };
+
+    let h = handle.clone();
+    let fut = exited.select2(timer).then(move |res| -> Box<Future<Item = _, Error = _>> {
+        match res {
+            Ok(Either::A((exit, _))) => Box::new(future::ok(exit)),
+            Ok(Either::B(_)) => match slot.borrow_mut().take() {
+                Some(child) => Box::new(stop.stop(child, &h).map(move |_| Some(Exit::TimedOut(timeout)))),
+                None => Box::new(future::ok(None)),
+            },
+            Err(Either::A((err, _))) | Err(Either::B((err, _))) => Box::new(future::err(err)),
+        }
+    });
+    Box::new(fut)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::cell::Cell;
+    use std::process::Command;
+
+    use tokio_core::reactor::Core;
+    use tokio_process::CommandExt;
+
+    use super::*;
+
+    #[test]
+    fn restarts_failing_commands() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let starts = Rc::new(Cell::new(0));
+        let s = starts.clone();
+        let h = handle.clone();
+        let spawn = move || {
+            s.set(s.get() + 1);
+            Command::new("false").spawn_async_with_handle(h.new_tokio_handle())
+        };
+        let policy = Policy {
+            restart: Restart::OnFailure,
+            max_restarts: Some(2),
+            delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(20),
+            timeout: None,
+        };
+
+        let supervisor = Supervisor::start(spawn, policy, Stop::default(), "false".to_owned(), &handle).unwrap();
+        core.run(Timeout::new(Duration::from_millis(500), &handle).unwrap()).unwrap();
+
+        assert_eq!(starts.get(), 3);
+        assert!(supervisor.stop().is_none());
+    }
+
+    #[test]
+    fn hands_out_running_commands() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let h = handle.clone();
+        let spawn = move || Command::new("sleep").arg("10").spawn_async_with_handle(h.new_tokio_handle());
+        let supervisor = Supervisor::start(spawn, Policy::default(), Stop::default(), "sleep".to_owned(), &handle).unwrap();
+        core.turn(Some(Duration::from_millis(10)));
+
+        let mut child = supervisor.stop().unwrap();
+        child.kill().unwrap();
+        assert!(!core.run(child).unwrap().success());
+    }
+
+    #[test]
+    fn stops_commands_after_timeout() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let stop = Stop::default();
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        stop.prepare(&mut command);
+
+        let child = command.spawn_async_with_handle(handle.new_tokio_handle()).unwrap();
+        let slot = Rc::new(RefCell::new(Some(child)));
+        let timeout = Duration::from_millis(50);
+
+        let exit = core.run(wait(slot.clone(), Some(timeout), stop, &handle)).unwrap();
+        assert_eq!(exit, Some(Exit::TimedOut(timeout)));
+        assert!(slot.borrow().is_none());
+    }
+}
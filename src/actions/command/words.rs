@@ -0,0 +1,140 @@
+//! Splitting command lines into words like a POSIX shell does.
+
+use std::io;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    /// Between two words.
+    Delimiter,
+    /// Inside an unquoted part of a word.
+    Unquoted,
+    /// After a backslash in an unquoted part of a word.
+    UnquotedEscape,
+    /// Inside single quotes.
+    SingleQuoted,
+    /// Inside double quotes.
+    DoubleQuoted,
+    /// After a backslash inside double quotes.
+    DoubleQuotedEscape,
+}
+
+/// Splits the given command line into words, honoring single and double
+/// quotes as well as backslash escapes.
+///
+/// No expansions of any kind (variables, globs, `~`) are performed, run
+/// the command through the shell if they are required.
+pub fn split(line: &str) -> io::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut state = State::Delimiter;
+
+    for c in line.chars() {
+        state = match state {
+            State::Delimiter | State::Unquoted => match c {
+                '\'' => State::SingleQuoted,
+                '"' => State::DoubleQuoted,
+                '\\' => State::UnquotedEscape,
+                ' ' | '\t' | '\n' => {
+                    if state == State::Unquoted {
+                        words.push(word.split_off(0));
+                    }
+                    State::Delimiter
+                },
+                c => {
+                    word.push(c);
+                    State::Unquoted
+                },
+            },
+            State::UnquotedEscape => {
+                // A backslash-newline pair continues the line.
+                if c != '\n' {
+                    word.push(c);
+                }
+                State::Unquoted
+            },
+            State::SingleQuoted => match c {
+                '\'' => State::Unquoted,
+                c => {
+                    word.push(c);
+                    State::SingleQuoted
+                },
+            },
+            State::DoubleQuoted => match c {
+                '"' => State::Unquoted,
+                '\\' => State::DoubleQuotedEscape,
+                c => {
+                    word.push(c);
+                    State::DoubleQuoted
+                },
+            },
+            State::DoubleQuotedEscape => {
+                // Inside double quotes the backslash only escapes a few
+                // characters and is kept literally otherwise.
+                match c {
+                    '$' | '`' | '"' | '\\' => word.push(c),
+                    '\n' => {},
+                    c => {
+                        word.push('\\');
+                        word.push(c);
+                    },
+                }
+                State::DoubleQuoted
+            },
+        };
+    }
+
+    match state {
+        State::Delimiter => {},
+        State::Unquoted => words.push(word),
+        State::UnquotedEscape => return Err(invalid(line, "ends with a backslash")),
+        State::SingleQuoted | State::DoubleQuoted | State::DoubleQuotedEscape => {
+            return Err(invalid(line, "contains an unterminated quote"));
+        },
+    }
+
+    Ok(words)
+}
+
+fn invalid(line: &str, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Command line '{}' {}.", line, reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        split(line).unwrap()
+    }
+
+    #[test]
+    fn splits_words() {
+        assert_eq!(words("touch /tmp/test.txt"), vec!["touch", "/tmp/test.txt"]);
+        assert_eq!(words(" \tnotify-send  Work\t "), vec!["notify-send", "Work"]);
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn honors_quotes() {
+        assert_eq!(words("touch \"/tmp/my file\""), vec!["touch", "/tmp/my file"]);
+        assert_eq!(words("echo 'a \"b\" \\c'"), vec!["echo", "a \"b\" \\c"]);
+        assert_eq!(words("echo \"a \\\"b\\\" \\c\""), vec!["echo", "a \"b\" \\c"]);
+        assert_eq!(words("echo pre'quoted'\"word\"post ''"), vec!["echo", "prequotedwordpost", ""]);
+    }
+
+    #[test]
+    fn honors_escapes() {
+        assert_eq!(words("touch /tmp/my\\ file"), vec!["touch", "/tmp/my file"]);
+        assert_eq!(words("echo a\\\nb"), vec!["echo", "ab"]);
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!(split("echo \"unterminated").is_err());
+        assert!(split("echo 'unterminated").is_err());
+        assert!(split("echo trailing\\").is_err());
+    }
+}
@@ -0,0 +1,401 @@
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use futures::future;
+use futures::prelude::*;
+use serde_yaml::{self, Value};
+
+use multi::Multi;
+use paths::expand_home;
+use super::{Action, Event};
+
+pub const ACTION_NAME: &'static str = "file";
+
+/// The suffix of the backup of a swapped out file.
+const BACKUP_SUFFIX: &'static str = ".runtext-orig";
+
+/// The suffix of the marker of a swapped in file that had no original.
+const ABSENT_SUFFIX: &'static str = ".runtext-absent";
+
+/// The suffix of files being prepared before they are moved in place.
+const TEMP_SUFFIX: &'static str = ".runtext-tmp";
+
+/// An action that swaps files for context specific ones while the context
+/// is active, e.g. `~/.ssh/config` for `~/.ssh/config.office`.
+///
+/// Files are replaced with the given `content`, a `copy` of another file
+/// or a symlink to another file (`link`). On enter, the original file is
+/// moved to a backup next to it, with the suffix `.runtext-orig`, and is
+/// moved back on leave. Where there was no original file, a
+/// `.runtext-absent` marker is left instead and the file is removed on
+/// leave. Written and copied files take over the permissions of the
+/// original.
+///
+/// Relative `copy` and `link` paths are relative to the directory of the
+/// swapped file, just like the targets of relative symlinks.
+///
+/// Files still swapped when the action is created, because the daemon
+/// crashed or was killed while the context was active, are restored.
+#[derive(Debug)]
+pub struct FileAction {
+    swaps: Vec<Swap>,
+}
+
+/// A file swapped in while the context is active.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Swap {
+    path: PathBuf,
+    source: Source,
+}
+
+/// Where the contents of a swapped in file come from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    /// The file is written with the given content.
+    Content(String),
+
+    /// The file is a copy of the given one.
+    Copy(PathBuf),
+
+    /// The file is a symlink to the given one.
+    Link(PathBuf),
+}
+
+#[derive(Debug, Deserialize)]
+struct SwapConfig {
+    path: String,
+    content: Option<String>,
+    copy: Option<String>,
+    link: Option<String>,
+}
+
+impl FileAction {
+    /// Creates a new `FileAction` performing the given swaps, restoring
+    /// files left swapped by a previous run.
+    pub fn new(swaps: Vec<Swap>) -> io::Result<Self> {
+        for swap in &swaps {
+            if swap.restore()? {
+                eprintln!("Restored {}, which was left swapped by a previous run.", swap.path.display());
+            }
+        }
+
+        Ok(FileAction { swaps })
+    }
+
+    /// Loads the action from the given configuration, either a single swap
+    /// or a list of them.
+    pub fn from_config(value: &Value) -> io::Result<Self> {
+        let cfg: Multi<SwapConfig> = serde_yaml::from_value(value.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid file configuration: {}", e)))?;
+
+        let swaps = cfg.into_iter()
+            .map(Swap::from_config)
+            .collect::<io::Result<Vec<_>>>()?;
+        Self::new(swaps)
+    }
+
+    fn enter_impl(&self) -> io::Result<()> {
+        for swap in &self.swaps {
+            swap.apply()?;
+        }
+
+        Ok(())
+    }
+
+    fn leave_impl(&self) -> io::Result<()> {
+        // Every file is restored, even if others fail.
+        let mut result = Ok(());
+        for swap in self.swaps.iter().rev() {
+            if let Err(err) = swap.restore() {
+                eprintln!("Failed to restore {}: {}.", swap.path.display(), err);
+                result = Err(err);
+            }
+        }
+
+        result
+    }
+}
+
+impl Action for FileAction {
+    fn enter(&mut self, _: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        Box::new(future::result(self.enter_impl()))
+    }
+
+    fn leave(&mut self, _: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        Box::new(future::result(self.leave_impl()))
+    }
+}
+
+impl Swap {
+    /// Creates a new swap of the file at the given path.
+    ///
+    /// A relative `Source::Copy` is resolved against the directory of `path`.
+    pub fn new(path: &str, source: Source) -> Self {
+        let path = expand_home(path);
+        let source = match source {
+            Source::Copy(ref copy) if copy.is_relative() => {
+                Source::Copy(path.parent().unwrap_or(Path::new("")).join(copy))
+            },
+            source => source,
+        };
+
+        Swap { path, source }
+    }
+
+    fn from_config(cfg: SwapConfig) -> io::Result<Self> {
+        let source = match (cfg.content, cfg.copy, cfg.link) {
+            (Some(content), None, None) => Source::Content(content),
+            (None, Some(copy), None) => Source::Copy(expand_home(&copy)),
+            (None, None, Some(link)) => Source::Link(expand_home(&link)),
+            _ => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Exactly one of content, copy and link must be given for {}.", cfg.path),
+            )),
+        };
+
+        Ok(Swap::new(&cfg.path, source))
+    }
+
+    /// Moves the original file out of the way and puts the swapped in
+    /// one in its place.
+    fn apply(&self) -> io::Result<()> {
+        let backup = with_suffix(&self.path, BACKUP_SUFFIX);
+        let absent = with_suffix(&self.path, ABSENT_SUFFIX);
+        let temp = with_suffix(&self.path, TEMP_SUFFIX);
+
+        // The new file is prepared first, so a failure leaves the original
+        // untouched.
+        remove_if_exists(&temp)?;
+        self.source.create(&temp)?;
+
+        // A backup that already exists is from an earlier swap, which
+        // must not be overwritten with the swapped in file.
+        if !exists(&backup)? && !exists(&absent)? {
+            if exists(&self.path)? {
+                fs::rename(&self.path, &backup)?;
+            } else {
+                OpenOptions::new().write(true).create(true).open(&absent)?;
+            }
+        }
+
+        // Permissions of symlinks are those of their target, which must
+        // not be touched.
+        match (&self.source, fs::metadata(&backup)) {
+            (&Source::Link(_), _) | (_, Err(_)) => {},
+            (_, Ok(meta)) => fs::set_permissions(&temp, meta.permissions())?,
+        }
+
+        fs::rename(&temp, &self.path)
+    }
+
+    /// Puts the original file back in place, if the file is swapped.
+    ///
+    /// Returns whether there was anything to restore.
+    fn restore(&self) -> io::Result<bool> {
+        let backup = with_suffix(&self.path, BACKUP_SUFFIX);
+        let absent = with_suffix(&self.path, ABSENT_SUFFIX);
+        remove_if_exists(&with_suffix(&self.path, TEMP_SUFFIX))?;
+
+        if exists(&backup)? {
+            fs::rename(&backup, &self.path)?;
+            Ok(true)
+        } else if exists(&absent)? {
+            remove_if_exists(&self.path)?;
+            fs::remove_file(&absent)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+impl Source {
+    fn create(&self, path: &Path) -> io::Result<()> {
+        match *self {
+            Source::Content(ref content) => {
+                let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+                file.write_all(content.as_bytes())
+            },
+            Source::Copy(ref source) => fs::copy(source, path).map(|_| ()),
+            Source::Link(ref target) => symlink(target, path),
+        }
+    }
+}
+
+/// Checks whether anything exists at the given path, without following
+/// symlinks.
+fn exists(path: &Path) -> io::Result<bool> {
+    match fs::symlink_metadata(path) {
+        Ok(_) => Ok(true),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+
+    PathBuf::from(path)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, path: &Path) -> io::Result<()> {
+    ::std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, path: &Path) -> io::Result<()> {
+    ::std::os::windows::fs::symlink_file(target, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use serde_yaml::Mapping;
+
+    use super::*;
+
+    fn event() -> Event {
+        Event {
+            context: "Office".to_owned(),
+            trigger: "wifi".to_owned(),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("runtext-file-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn swap(path: &Path, source: Source) -> Swap {
+        Swap::new(path.to_str().unwrap(), source)
+    }
+
+    #[test]
+    fn load_cfg() {
+        let mut map = Mapping::new();
+        map.insert("path".into(), "/tmp/runtext-file-cfg/config".into());
+        map.insert("link".into(), "config.office".into());
+        let mut copy = Mapping::new();
+        copy.insert("path".into(), "/tmp/runtext-file-cfg/config".into());
+        copy.insert("copy".into(), "config.office".into());
+
+        let cfg = Value::Sequence(vec![Value::Mapping(map), Value::Mapping(copy)]);
+        let action = FileAction::from_config(&cfg).unwrap();
+        assert_eq!(action.swaps, vec![
+            Swap::new("/tmp/runtext-file-cfg/config", Source::Link(PathBuf::from("config.office"))),
+            Swap::new("/tmp/runtext-file-cfg/config", Source::Copy(PathBuf::from("/tmp/runtext-file-cfg/config.office"))),
+        ]);
+    }
+
+    #[test]
+    fn load_cfg_errors() {
+        let mut map = Mapping::new();
+        map.insert("path".into(), "/tmp/runtext-file-cfg/config".into());
+        assert!(FileAction::from_config(&Value::Mapping(map.clone())).is_err());
+
+        map.insert("content".into(), "Host *".into());
+        map.insert("copy".into(), "config.office".into());
+        assert!(FileAction::from_config(&Value::Mapping(map)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn swaps_and_restores_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("swap");
+        let config = dir.join("config");
+        let office = dir.join("config.office");
+        fs::write(&config, "original").unwrap();
+        fs::set_permissions(&config, fs::Permissions::from_mode(0o600)).unwrap();
+        fs::write(&office, "office").unwrap();
+
+        let mut action = FileAction::new(vec![
+            swap(&config, Source::Copy(PathBuf::from("config.office"))),
+            swap(&dir.join("new"), Source::Content("created".to_owned())),
+        ]).unwrap();
+
+        action.enter(&event()).wait().unwrap();
+        assert_eq!(fs::read_to_string(&config).unwrap(), "office");
+        assert_eq!(fs::metadata(&config).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(with_suffix(&config, BACKUP_SUFFIX)).unwrap(), "original");
+        assert_eq!(fs::read_to_string(dir.join("new")).unwrap(), "created");
+
+        // Entering again must not overwrite the backup.
+        action.enter(&event()).wait().unwrap();
+        action.leave(&event()).wait().unwrap();
+        assert_eq!(fs::read_to_string(&config).unwrap(), "original");
+        assert!(!dir.join("new").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_files() {
+        let dir = temp_dir("link");
+        let config = dir.join("config");
+        fs::write(&config, "original").unwrap();
+        fs::write(dir.join("config.office"), "office").unwrap();
+
+        let mut action = FileAction::new(vec![swap(&config, Source::Link(PathBuf::from("config.office")))]).unwrap();
+        action.enter(&event()).wait().unwrap();
+        assert_eq!(fs::read_link(&config).unwrap(), PathBuf::from("config.office"));
+        assert_eq!(fs::read_to_string(&config).unwrap(), "office");
+
+        action.leave(&event()).wait().unwrap();
+        assert_eq!(fs::read_to_string(&config).unwrap(), "original");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recovers_from_crashes() {
+        let dir = temp_dir("crash");
+        let config = dir.join("config");
+        fs::write(&config, "original").unwrap();
+
+        let swaps = vec![swap(&config, Source::Content("office".to_owned()))];
+        let mut action = FileAction::new(swaps.clone()).unwrap();
+        action.enter(&event()).wait().unwrap();
+        // The daemon dies without leaving the context.
+        drop(action);
+
+        FileAction::new(swaps).unwrap();
+        assert_eq!(fs::read_to_string(&config).unwrap(), "original");
+        assert!(!with_suffix(&config, BACKUP_SUFFIX).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_original_if_source_is_missing() {
+        let dir = temp_dir("missing");
+        let config = dir.join("config");
+        fs::write(&config, "original").unwrap();
+
+        let mut action = FileAction::new(vec![swap(&config, Source::Copy(dir.join("nonexistent")))]).unwrap();
+        assert!(action.enter(&event()).wait().is_err());
+        assert_eq!(fs::read_to_string(&config).unwrap(), "original");
+        assert!(!with_suffix(&config, BACKUP_SUFFIX).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -3,12 +3,33 @@ use std::io;
 use futures::prelude::*;
 
 pub mod command;
+pub mod file;
+#[cfg(unix)]
+pub mod notify;
+#[cfg(unix)]
+pub mod systemd;
 
 /// Represents an action to be executed upon a context transition.
 pub trait Action {
-    /// Asynchronously start executing the action.
-    fn enter(&mut self) -> Box<Future<Item = (), Error = io::Error>>;
+    /// Starts executing the action when the context is entered.
+    ///
+    /// The future resolves once entering is done, e.g. when a oneshot
+    /// command has exited.
+    fn enter(&mut self, event: &Event) -> Box<Future<Item = (), Error = io::Error>>;
 
-    /// Synchronously stop executing the action.
-    fn leave(&mut self) -> Box<Future<Item = (), Error = io::Error>>;
-}
\ No newline at end of file
+    /// Stops executing the action when the context is left.
+    ///
+    /// The future resolves once leaving is done, e.g. when the leave
+    /// command has completed, and fails if it didn't succeed.
+    fn leave(&mut self, event: &Event) -> Box<Future<Item = (), Error = io::Error>>;
+}
+
+/// Describes the context transition an action is executed for.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Event {
+    /// The name of the context being entered or left.
+    pub context: String,
+
+    /// The name of the evidence source whose change caused the transition.
+    pub trigger: String,
+}
@@ -0,0 +1,398 @@
+use std::io;
+use std::time::Duration;
+
+use futures::future;
+use futures::prelude::*;
+use humantime;
+use serde_yaml::{self, Value as YamlValue};
+use tokio_core::reactor::Handle;
+
+use context::config_error;
+use dbus::{Connection, Message, Value};
+use super::{Action, Event};
+
+pub const ACTION_NAME: &'static str = "notify";
+
+const NOTIFICATIONS_NAME: &'static str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &'static str = "/org/freedesktop/Notifications";
+
+const DEFAULT_APP_NAME: &'static str = "runtext";
+
+/// An action that shows a desktop notification when the context is
+/// entered and/or left.
+///
+/// Notifications are sent to the notification server on the session
+/// bus, see https://specifications.freedesktop.org/notification-spec/.
+/// Their summary and body may refer to the `{context}` being entered or
+/// left, the `{event}` (`enter` or `leave`) and the `{trigger}` that
+/// caused it. Literal braces are written as `{{` and `}}`.
+///
+/// The session bus is found through `DBUS_SESSION_BUS_ADDRESS`, unless
+/// another bus is given by `address`, e.g. `unix:path=/run/user/1000/bus`.
+#[derive(Debug)]
+pub struct NotifyAction {
+    enter: Option<Notification>,
+    leave: Option<Notification>,
+    app_name: String,
+    icon: String,
+    urgency: Option<Urgency>,
+    timeout: Option<Duration>,
+    address: Option<String>,
+    handle: Handle,
+}
+
+/// The text of a notification.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Notification {
+    summary: Template,
+    body: Template,
+}
+
+/// How urgent notifications are, which servers may use to decide how
+/// to show them.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct NotifyConfig {
+    enter: Option<NotificationConfig>,
+    leave: Option<NotificationConfig>,
+    app_name: Option<String>,
+    icon: Option<String>,
+    urgency: Option<Urgency>,
+    timeout: Option<String>,
+    address: Option<String>,
+}
+
+/// A notification, given either as just its summary or with a body.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum NotificationConfig {
+    Summary(String),
+    Full {
+        summary: String,
+        #[serde(default)]
+        body: String,
+    },
+}
+
+/// A text with placeholders for the properties of an event.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Template(Vec<Part>);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Part {
+    Text(String),
+    Context,
+    Event,
+    Trigger,
+}
+
+impl NotifyAction {
+    /// Creates a new `NotifyAction` showing the given notifications.
+    pub fn new(enter: Option<Notification>, leave: Option<Notification>, handle: Handle) -> Self {
+        NotifyAction {
+            enter,
+            leave,
+            app_name: DEFAULT_APP_NAME.to_owned(),
+            icon: String::new(),
+            urgency: None,
+            timeout: None,
+            address: None,
+            handle,
+        }
+    }
+
+    /// Loads the action from the given configuration.
+    ///
+    /// A plain string is the summary of the notification shown on enter.
+    pub fn from_config(value: &YamlValue, handle: Handle) -> io::Result<Self> {
+        if let YamlValue::String(ref summary) = *value {
+            return Ok(Self::new(Some(Notification::new(summary, "")?), None, handle));
+        }
+
+        let cfg: NotifyConfig = serde_yaml::from_value(value.clone()).map_err(|e| config_error(ACTION_NAME, e))?;
+        if cfg.enter.is_none() && cfg.leave.is_none() {
+            return Err(config_error(ACTION_NAME, "at least one of enter and leave must be given"));
+        }
+
+        let enter = match cfg.enter {
+            Some(enter) => Some(enter.into_notification()?),
+            None => None,
+        };
+        let leave = match cfg.leave {
+            Some(leave) => Some(leave.into_notification()?),
+            None => None,
+        };
+
+        let mut action = Self::new(enter, leave, handle);
+        if let Some(app_name) = cfg.app_name {
+            action.app_name = app_name;
+        }
+        if let Some(icon) = cfg.icon {
+            action.icon = icon;
+        }
+        action.urgency = cfg.urgency;
+        if let Some(timeout) = cfg.timeout {
+            action.timeout = Some(humantime::parse_duration(&timeout).map_err(|e| config_error(ACTION_NAME, e))?);
+        }
+        if let Some(ref address) = cfg.address {
+            action.address(address);
+        }
+
+        Ok(action)
+    }
+
+    /// Sets the address of the bus to send notifications over, instead
+    /// of the session bus.
+    pub fn address(&mut self, address: &str) -> &mut Self {
+        self.address = Some(address.to_owned());
+        self
+    }
+
+    fn notify(&self, notification: Option<&Notification>, kind: &str, event: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        let notification = match notification {
+            Some(notification) => notification,
+            None => return Box::new(future::ok(())),
+        };
+
+        let hints = self.urgency.iter()
+            .map(|urgency| Value::DictEntry(
+                Box::new(Value::String("urgency".to_owned())),
+                Box::new(Value::Variant(Box::new(Value::Byte(*urgency as u8)))),
+            ))
+            .collect();
+        let timeout = self.timeout
+            .map(|t| (t.as_secs() * 1000 + u64::from(t.subsec_nanos() / 1_000_000)).min(i32::max_value() as u64) as i32)
+            .unwrap_or(-1);
+
+        let notify = Message::method_call(
+            NOTIFICATIONS_NAME,
+            NOTIFICATIONS_PATH,
+            NOTIFICATIONS_NAME,
+            "Notify",
+            vec![
+                Value::String(self.app_name.clone()),
+                Value::UInt32(0),
+                Value::String(self.icon.clone()),
+                Value::String(notification.summary.render(kind, event)),
+                Value::String(notification.body.render(kind, event)),
+                Value::Array("s".to_owned(), vec![]),
+                Value::Array("{sv}".to_owned(), hints),
+                Value::Int32(timeout),
+            ],
+        );
+
+        let conn = match self.address {
+            Some(ref address) => Connection::open(address, &self.handle),
+            None => Connection::session(&self.handle),
+        };
+        let fut = conn
+            .and_then(move |conn| conn.call(notify))
+            .map(|_| ());
+        Box::new(fut)
+    }
+}
+
+impl Action for NotifyAction {
+    fn enter(&mut self, event: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        self.notify(self.enter.as_ref(), "enter", event)
+    }
+
+    fn leave(&mut self, event: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        self.notify(self.leave.as_ref(), "leave", event)
+    }
+}
+
+impl Notification {
+    /// Creates a new notification from the given summary and body
+    /// templates.
+    pub fn new(summary: &str, body: &str) -> io::Result<Self> {
+        Ok(Notification {
+            summary: Template::parse(summary)?,
+            body: Template::parse(body)?,
+        })
+    }
+}
+
+impl NotificationConfig {
+    fn into_notification(self) -> io::Result<Notification> {
+        match self {
+            NotificationConfig::Summary(summary) => Notification::new(&summary, ""),
+            NotificationConfig::Full { summary, body } => Notification::new(&summary, &body),
+        }
+    }
+}
+
+impl Template {
+    fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid notification text '{}': {}.", text, msg),
+        );
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(invalid("unmatched '{'")),
+                        }
+                    }
+                    let part = match name.trim() {
+                        "context" => Part::Context,
+                        "event" => Part::Event,
+                        "trigger" => Part::Trigger,
+                        _ => return Err(invalid(&format!("unknown placeholder '{{{}}}'", name))),
+                    };
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(literal.split_off(0)));
+                    }
+                    parts.push(part);
+                },
+                '}' => return Err(invalid("unmatched '}'")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+
+        Ok(Template(parts))
+    }
+
+    fn render(&self, kind: &str, event: &Event) -> String {
+        self.0.iter()
+            .map(|part| match *part {
+                Part::Text(ref text) => text.as_str(),
+                Part::Context => event.context.as_str(),
+                Part::Event => kind,
+                Part::Trigger => event.trigger.as_str(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_yaml::Mapping;
+    use tokio_core::reactor::Core;
+
+    use dbus::mock::MockBus;
+    use super::*;
+
+    fn event() -> Event {
+        Event {
+            context: "Office".to_owned(),
+            trigger: "wifi".to_owned(),
+        }
+    }
+
+    #[test]
+    fn load_cfg() {
+        let core = Core::new().unwrap();
+
+        let action = NotifyAction::from_config(&YamlValue::String("Entered {context}".to_owned()), core.handle()).unwrap();
+        assert_eq!(action.enter, Some(Notification::new("Entered {context}", "").unwrap()));
+        assert_eq!(action.leave, None);
+
+        let mut leave = Mapping::new();
+        leave.insert("summary".into(), "Left {context}".into());
+        leave.insert("body".into(), "{trigger} changed".into());
+        let mut map = Mapping::new();
+        map.insert("leave".into(), YamlValue::Mapping(leave));
+        map.insert("urgency".into(), "critical".into());
+        map.insert("timeout".into(), "5s".into());
+
+        let action = NotifyAction::from_config(&YamlValue::Mapping(map), core.handle()).unwrap();
+        assert_eq!(action.enter, None);
+        assert_eq!(action.leave, Some(Notification::new("Left {context}", "{trigger} changed").unwrap()));
+        assert_eq!(action.urgency, Some(Urgency::Critical));
+        assert_eq!(action.timeout, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn load_cfg_errors() {
+        let core = Core::new().unwrap();
+
+        let mut map = Mapping::new();
+        map.insert("icon".into(), "dialog-information".into());
+        assert!(NotifyAction::from_config(&YamlValue::Mapping(map), core.handle()).is_err());
+
+        let placeholder = YamlValue::String("Entered {ctx}".to_owned());
+        assert!(NotifyAction::from_config(&placeholder, core.handle()).is_err());
+    }
+
+    #[test]
+    fn renders_templates() {
+        let template = Template::parse("{event}ed {context} ({{{trigger}}})").unwrap();
+        assert_eq!(template.render("enter", &event()), "entered Office ({wifi})");
+
+        assert!(Template::parse("{context").is_err());
+        assert!(Template::parse("context}").is_err());
+    }
+
+    #[test]
+    fn sends_notifications() {
+        let mut core = Core::new().unwrap();
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let c = calls.clone();
+        let bus = MockBus::start(move |call| {
+            c.lock().unwrap().push(call.clone());
+            vec![Message::method_return(call, vec![Value::UInt32(1)])]
+        });
+
+        let enter = Notification::new("Entered {context}", "Because of {trigger}").unwrap();
+        let mut action = NotifyAction::new(Some(enter), None, core.handle());
+        action.address(bus.address());
+        action.urgency = Some(Urgency::Low);
+
+        core.run(action.enter(&event())).unwrap();
+        core.run(action.leave(&event())).unwrap();
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].destination.as_ref().unwrap(), NOTIFICATIONS_NAME);
+        assert_eq!(calls[0].member.as_ref().unwrap(), "Notify");
+        assert_eq!(calls[0].body[3], Value::String("Entered Office".to_owned()));
+        assert_eq!(calls[0].body[4], Value::String("Because of wifi".to_owned()));
+        assert_eq!(calls[0].body[7], Value::Int32(-1));
+    }
+
+    #[test]
+    fn reports_server_errors() {
+        let mut core = Core::new().unwrap();
+
+        let bus = MockBus::start(|call| vec![Message::error(call, "org.freedesktop.DBus.Error.ServiceUnknown", "No server")]);
+        let enter = Notification::new("Entered {context}", "").unwrap();
+        let mut action = NotifyAction::new(Some(enter), None, core.handle());
+        action.address(bus.address());
+
+        let err = core.run(action.enter(&event())).unwrap_err();
+        assert!(err.to_string().contains("ServiceUnknown"));
+    }
+}
@@ -0,0 +1,414 @@
+use std::io;
+use std::time::Duration;
+
+use futures::future::{self, Either, Loop};
+use futures::prelude::*;
+use futures::stream;
+use futures::unsync::mpsc::UnboundedReceiver;
+use humantime;
+use serde_yaml::{self, Value as YamlValue};
+use tokio_core::reactor::{Handle, Timeout};
+
+use dbus::{Connection, Message, Value};
+use context::config_error;
+use multi::Multi;
+use super::{Action, Event};
+
+pub const ACTION_NAME: &'static str = "systemd";
+
+const SYSTEMD_NAME: &'static str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &'static str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &'static str = "org.freedesktop.systemd1.Manager";
+
+/// The job modes systemd understands, see `systemctl --job-mode`.
+const JOB_MODES: &'static [&'static str] = &[
+    "replace",
+    "fail",
+    "isolate",
+    "ignore-dependencies",
+    "ignore-requirements",
+];
+
+/// An action that starts systemd units when the context is entered and
+/// stops them again when it is left.
+///
+/// Units are started in the given order and stopped in reverse. Each
+/// unit's job has to finish before the next one is queued, a job that
+/// fails, e.g. because the unit doesn't start up, fails the action.
+///
+/// Units are managed by the user's service manager, which is reached
+/// over the session bus, unless `manager: system` is set.
+#[derive(Debug)]
+pub struct SystemdAction {
+    units: Vec<String>,
+    manager: Manager,
+    job_mode: String,
+    timeout: Option<Duration>,
+    address: Option<String>,
+    handle: Handle,
+}
+
+/// The service manager units are managed by.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Manager {
+    /// The user's service manager, i.e. `systemctl --user`.
+    User,
+
+    /// The system's service manager.
+    System,
+}
+
+#[derive(Debug, Deserialize)]
+struct SystemdConfig {
+    units: Multi<String>,
+    manager: Option<Manager>,
+    job_mode: Option<String>,
+    timeout: Option<String>,
+    address: Option<String>,
+}
+
+impl SystemdAction {
+    /// Creates a new `SystemdAction` managing the given units.
+    ///
+    /// Unit names without a type suffix refer to services, just like
+    /// they do with `systemctl`.
+    pub fn new(units: Vec<String>, manager: Manager, handle: Handle) -> Self {
+        let units = units.into_iter()
+            .map(|unit| if unit.contains('.') { unit } else { format!("{}.service", unit) })
+            .collect();
+
+        SystemdAction {
+            units,
+            manager,
+            job_mode: "replace".to_owned(),
+            timeout: None,
+            address: None,
+            handle,
+        }
+    }
+
+    /// Loads the action from the given configuration.
+    ///
+    /// A plain unit name or list of them stands for user units.
+    pub fn from_config(value: &YamlValue, handle: Handle) -> io::Result<Self> {
+        if let Ok(units) = serde_yaml::from_value::<Multi<String>>(value.clone()) {
+            return Self::from_units(units, Manager::User, handle);
+        }
+
+        let cfg: SystemdConfig = serde_yaml::from_value(value.clone()).map_err(|e| config_error(ACTION_NAME, e))?;
+        let mut action = Self::from_units(cfg.units, cfg.manager.unwrap_or(Manager::User), handle)?;
+        if let Some(mode) = cfg.job_mode {
+            if !JOB_MODES.contains(&mode.as_str()) {
+                return Err(config_error(ACTION_NAME, format!("unknown job mode '{}'", mode)));
+            }
+            action.job_mode = mode;
+        }
+        if let Some(timeout) = cfg.timeout {
+            action.timeout = Some(humantime::parse_duration(&timeout).map_err(|e| config_error(ACTION_NAME, e))?);
+        }
+        if let Some(ref address) = cfg.address {
+            action.address(address);
+        }
+
+        Ok(action)
+    }
+
+    fn from_units(units: Multi<String>, manager: Manager, handle: Handle) -> io::Result<Self> {
+        let units = units.into_iter().collect::<Vec<_>>();
+        if units.is_empty() || units.iter().any(|unit| unit.trim().is_empty()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid systemd configuration: missing unit name"));
+        }
+
+        Ok(Self::new(units, manager, handle))
+    }
+
+    /// Sets the address of the bus to reach the service manager over,
+    /// instead of the session or system bus.
+    pub fn address(&mut self, address: &str) -> &mut Self {
+        self.address = Some(address.to_owned());
+        self
+    }
+
+    /// Runs a job of the given kind (`StartUnit` or `StopUnit`) for each
+    /// unit in turn.
+    fn run_jobs(&self, method: &'static str, units: Vec<String>) -> Box<Future<Item = (), Error = io::Error>> {
+        let conn = match (self.address.as_ref(), self.manager) {
+            (Some(address), _) => Connection::open(address, &self.handle),
+            (None, Manager::User) => Connection::session(&self.handle),
+            (None, Manager::System) => Connection::system(&self.handle),
+        };
+
+        let mode = self.job_mode.clone();
+        let timeout = self.timeout;
+        let handle = self.handle.clone();
+        let fut = conn
+            .and_then(|conn| {
+                // The manager only announces finished jobs to subscribers,
+                // which must listen before queueing any to not miss them.
+                let rule = format!(
+                    "type='signal',sender='{}',path='{}',interface='{}',member='JobRemoved'",
+                    SYSTEMD_NAME, SYSTEMD_PATH, MANAGER_INTERFACE,
+                );
+                let subscribe = Message::method_call(SYSTEMD_NAME, SYSTEMD_PATH, MANAGER_INTERFACE, "Subscribe", vec![]);
+
+                conn.signals(&rule)
+                    .join(conn.call(subscribe))
+                    .map(move |(signals, _)| (conn, signals))
+            })
+            .and_then(move |(conn, signals)| {
+                stream::iter_ok(units)
+                    .fold(signals, move |signals, unit| {
+                        let job = Message::method_call(
+                            SYSTEMD_NAME,
+                            SYSTEMD_PATH,
+                            MANAGER_INTERFACE,
+                            method,
+                            vec![Value::String(unit.clone()), Value::String(mode.clone())],
+                        );
+
+                        let h = handle.clone();
+                        conn.call(job)
+                            .and_then(|reply| match reply.body.first().and_then(Value::as_str) {
+                                Some(job) => Ok(job.to_owned()),
+                                None => Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid reply to job request.")),
+                            })
+                            .and_then(move |job| wait_for_job(signals, job, unit, method, timeout, &h))
+                    })
+            })
+            .map(|_| ());
+
+        Box::new(fut)
+    }
+}
+
+impl Action for SystemdAction {
+    fn enter(&mut self, _: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        self.run_jobs("StartUnit", self.units.clone())
+    }
+
+    fn leave(&mut self, _: &Event) -> Box<Future<Item = (), Error = io::Error>> {
+        self.run_jobs("StopUnit", self.units.iter().rev().cloned().collect())
+    }
+}
+
+/// Waits for the given job to be removed and checks its result.
+///
+/// Resolves to the stream of signals, for waiting on further jobs.
+fn wait_for_job(
+    signals: UnboundedReceiver<Message>,
+    job: String,
+    unit: String,
+    method: &'static str,
+    timeout: Option<Duration>,
+    handle: &Handle,
+) -> Box<Future<Item = UnboundedReceiver<Message>, Error = io::Error>> {
+    let action = if method == "StartUnit" { "Starting" } else { "Stopping" };
+
+    let removed = future::loop_fn(signals, move |signals| {
+        let job = job.clone();
+
+        signals.into_future()
+            .map_err(|_| unreachable!())
+            .and_then(move |(signal, signals)| {
+                let signal = signal.ok_or_else(|| io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "D-Bus connection closed while waiting for job.",
+                ))?;
+                if !signal.is_signal(MANAGER_INTERFACE, "JobRemoved") {
+                    return Ok(Loop::Continue(signals));
+                }
+
+                // JobRemoved carries the job's id, path, unit and result.
+                match (signal.body.get(1).and_then(Value::as_str), signal.body.get(3).and_then(Value::as_str)) {
+                    (Some(path), Some(result)) if path == job => Ok(Loop::Break((result.to_owned(), signals))),
+                    _ => Ok(Loop::Continue(signals)),
+                }
+            })
+    });
+
+    let u = unit.clone();
+    let checked = removed.and_then(move |(result, signals)| match result.as_str() {
+        // Jobs are skipped if there's nothing to do, e.g. when stopping
+        // a unit that isn't running.
+        "done" | "skipped" => Ok(signals),
+        _ => Err(io::Error::new(io::ErrorKind::Other, format!("{} unit {} failed: {}.", action, u, result))),
+    });
+
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Box::new(checked),
+    };
+    let timer = match Timeout::new(timeout, handle) {
+        Ok(timer) => timer,
+        Err(err) => return Box::new(future::err(err)),
+    };
+
+    let fut = checked.select2(timer).then(move |res| match res {
+        Ok(Either::A((signals, _))) => Ok(signals),
+        Ok(Either::B(_)) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("{} unit {} didn't finish within {}.", action, unit, humantime::format_duration(timeout)),
+        )),
+        Err(Either::A((err, _))) | Err(Either::B((err, _))) => Err(err),
+    });
+    Box::new(fut)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_yaml::Mapping;
+    use tokio_core::reactor::Core;
+
+    use dbus::mock::MockBus;
+    use super::*;
+
+    fn event() -> Event {
+        Event {
+            context: "Office".to_owned(),
+            trigger: "wifi".to_owned(),
+        }
+    }
+
+    /// Starts a service manager finishing jobs with the given result.
+    fn manager(result: &'static str, calls: Arc<Mutex<Vec<Message>>>) -> MockBus {
+        MockBus::start(move |call| {
+            calls.lock().unwrap().push(call.clone());
+
+            let method = call.member.clone().unwrap_or_default();
+            if method != "StartUnit" && method != "StopUnit" {
+                return vec![Message::method_return(call, vec![])];
+            }
+
+            let id = calls.lock().unwrap().len() as u32;
+            let job = format!("{}/job/{}", SYSTEMD_PATH, id);
+            let unit = call.body[0].clone();
+            vec![
+                Message::method_return(call, vec![Value::ObjectPath(job.clone())]),
+                // Jobs of other clients finish as well.
+                Message::signal(SYSTEMD_PATH, MANAGER_INTERFACE, "JobRemoved", vec![
+                    Value::UInt32(1000 + id),
+                    Value::ObjectPath(format!("{}/job/{}", SYSTEMD_PATH, 1000 + id)),
+                    Value::String("other.service".to_owned()),
+                    Value::String("failed".to_owned()),
+                ]),
+                Message::signal(SYSTEMD_PATH, MANAGER_INTERFACE, "JobRemoved", vec![
+                    Value::UInt32(id),
+                    Value::ObjectPath(job),
+                    unit,
+                    Value::String(result.to_owned()),
+                ]),
+            ]
+        })
+    }
+
+    fn jobs(calls: &[Message]) -> Vec<(String, String)> {
+        calls.iter()
+            .filter(|call| call.member.as_ref().map_or(false, |m| m.ends_with("Unit")))
+            .map(|call| (call.member.clone().unwrap(), call.body[0].as_str().unwrap().to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn load_cfg() {
+        let core = Core::new().unwrap();
+
+        let action = SystemdAction::from_config(&YamlValue::String("syncthing".to_owned()), core.handle()).unwrap();
+        assert_eq!(action.units, vec!["syncthing.service"]);
+        assert_eq!(action.manager, Manager::User);
+
+        let mut map = Mapping::new();
+        map.insert("units".into(), YamlValue::Sequence(vec!["vpn.service".into(), "mount.target".into()]));
+        map.insert("manager".into(), "system".into());
+        map.insert("job_mode".into(), "fail".into());
+        map.insert("timeout".into(), "30s".into());
+
+        let action = SystemdAction::from_config(&YamlValue::Mapping(map), core.handle()).unwrap();
+        assert_eq!(action.units, vec!["vpn.service", "mount.target"]);
+        assert_eq!(action.manager, Manager::System);
+        assert_eq!(action.job_mode, "fail");
+        assert_eq!(action.timeout, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn load_cfg_errors() {
+        let core = Core::new().unwrap();
+
+        assert!(SystemdAction::from_config(&YamlValue::Sequence(vec![]), core.handle()).is_err());
+
+        let mut map = Mapping::new();
+        map.insert("units".into(), "syncthing".into());
+        map.insert("job_mode".into(), "whatever".into());
+        assert!(SystemdAction::from_config(&YamlValue::Mapping(map), core.handle()).is_err());
+    }
+
+    #[test]
+    fn starts_and_stops_units() {
+        let mut core = Core::new().unwrap();
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let bus = manager("done", calls.clone());
+        let mut action = SystemdAction::new(vec!["a".to_owned(), "b.timer".to_owned()], Manager::User, core.handle());
+        action.address(bus.address());
+
+        core.run(action.enter(&event())).unwrap();
+        core.run(action.leave(&event())).unwrap();
+
+        let calls = calls.lock().unwrap();
+        assert!(calls.iter().any(|call| call.member.as_ref().unwrap() == "Subscribe"));
+        assert_eq!(jobs(&calls), vec![
+            ("StartUnit".to_owned(), "a.service".to_owned()),
+            ("StartUnit".to_owned(), "b.timer".to_owned()),
+            ("StopUnit".to_owned(), "b.timer".to_owned()),
+            ("StopUnit".to_owned(), "a.service".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn reports_failed_jobs() {
+        let mut core = Core::new().unwrap();
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let bus = manager("failed", calls.clone());
+        let mut action = SystemdAction::new(vec!["a".to_owned(), "b".to_owned()], Manager::User, core.handle());
+        action.address(bus.address());
+
+        let err = core.run(action.enter(&event())).unwrap_err();
+        assert_eq!(err.to_string(), "Starting unit a.service failed: failed.");
+        assert_eq!(jobs(&calls.lock().unwrap()).len(), 1);
+    }
+
+    #[test]
+    fn reports_unknown_units() {
+        let mut core = Core::new().unwrap();
+
+        let bus = MockBus::start(|call| match call.member.as_ref().map(String::as_str) {
+            Some("StartUnit") => vec![Message::error(call, "org.freedesktop.systemd1.NoSuchUnit", "Unit a.service not found.")],
+            _ => vec![Message::method_return(call, vec![])],
+        });
+        let mut action = SystemdAction::new(vec!["a".to_owned()], Manager::User, core.handle());
+        action.address(bus.address());
+
+        let err = core.run(action.enter(&event())).unwrap_err();
+        assert!(err.to_string().contains("NoSuchUnit"));
+    }
+
+    #[test]
+    fn times_out_waiting_for_jobs() {
+        let mut core = Core::new().unwrap();
+
+        // The job is queued, but never finishes.
+        let bus = MockBus::start(|call| match call.member.as_ref().map(String::as_str) {
+            Some("StartUnit") => vec![Message::method_return(call, vec![Value::ObjectPath("/job/1".to_owned())])],
+            _ => vec![Message::method_return(call, vec![])],
+        });
+        let mut action = SystemdAction::new(vec!["a".to_owned()], Manager::User, core.handle());
+        action.address(bus.address());
+        action.timeout = Some(Duration::from_millis(50));
+
+        let err = core.run(action.enter(&event())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
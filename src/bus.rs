@@ -0,0 +1,90 @@
+//! A bus the context drivers publish their activity on.
+//!
+//! This allows contexts to depend on each other, e.g. an "office and docked"
+//! context that is built from the "office" and "docked" contexts.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use triggers::Activity;
+
+/// A shared bus carrying the activity of all running contexts.
+///
+/// The bus is cheap to clone, all clones refer to the same state.
+#[derive(Clone, Debug, Default)]
+pub struct Bus(Rc<RefCell<Inner>>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    states: HashMap<String, Activity>,
+    subscribers: Vec<(String, UnboundedSender<Activity>)>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus::default()
+    }
+
+    /// Publishes the activity of the context with the given name
+    /// to all subscribers.
+    pub fn publish(&self, context: &str, activity: Activity) {
+        let mut inner = self.0.borrow_mut();
+
+        inner.states.insert(context.to_owned(), activity.clone());
+        inner.subscribers.retain(|&(ref name, ref tx)| {
+            name != context || tx.unbounded_send(activity.clone()).is_ok()
+        });
+    }
+
+    /// Subscribes to the activity changes of the context with the given name.
+    ///
+    /// If the context is currently active, this is signaled right away.
+    pub fn subscribe(&self, context: &str) -> UnboundedReceiver<Activity> {
+        let mut inner = self.0.borrow_mut();
+        let (tx, rx) = mpsc::unbounded();
+
+        if inner.states.get(context) == Some(&Activity::Active) {
+            // Cannot fail, we're holding the receiver.
+            let _ = tx.unbounded_send(Activity::Active);
+        }
+        inner.subscribers.push((context.to_owned(), tx));
+
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+
+    use super::*;
+
+    #[test]
+    fn publish_subscribe() {
+        let bus = Bus::new();
+        let rx = bus.subscribe("Office");
+
+        bus.publish("Docked", Activity::Active);
+        bus.publish("Office", Activity::Active);
+        bus.publish("Office", Activity::Inactive);
+        drop(bus);
+
+        let received = rx.collect().wait().unwrap();
+        assert_eq!(received, vec![Activity::Active, Activity::Inactive]);
+    }
+
+    #[test]
+    fn subscribe_receives_current_state() {
+        let bus = Bus::new();
+        bus.publish("Office", Activity::Active);
+
+        let rx = bus.subscribe("Office");
+        drop(bus);
+
+        let received = rx.collect().wait().unwrap();
+        assert_eq!(received, vec![Activity::Active]);
+    }
+}
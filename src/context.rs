@@ -1,10 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
+use std::io;
 
 use serde_yaml;
 
 use multi::Multi;
+use triggers::context::{TRIGGER_NAME as CONTEXT_TRIGGER_NAME, ContextTrigger};
 
 /// Configuration.
 pub type Config = Multi<Context>;
@@ -44,29 +46,89 @@ pub enum TriggerBehavior {
 /// The ways a context configuration can be invalid.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ValidationError {
+    MissingName,
+    DuplicateName,
     MissingTriggers,
     MissingActions,
+    UnknownDependency,
+    DependencyCycle,
 }
 
 impl Config {
     pub fn validate(&self) -> Result<(), ValidationError> {
-        match *self {
-            Multi::Single(ref ctx) => ctx.validate(),
-            Multi::Multiple(ref ctxs) => {
-                for context in ctxs {
-                    if let Err(err) = context.validate() {
-                        return Err(err);
-                    }
-                }
-
-                Ok(())
-            },
+        for context in self {
+            if let Err(err) = context.validate() {
+                return Err(err);
+            }
+        }
+
+        self.validate_names()?;
+        self.validate_dependencies()
+    }
+
+    /// Ensures the contexts can be told apart by their names, which they
+    /// are published under and depended upon by.
+    fn validate_names(&self) -> Result<(), ValidationError> {
+        let mut names = HashSet::new();
+        if self.iter().all(|ctx| names.insert(ctx.name.as_str())) {
+            Ok(())
+        } else {
+            Err(ValidationError::DuplicateName)
+        }
+    }
+
+    /// Ensures all contexts depended upon exist and that there are
+    /// no dependency cycles between the contexts.
+    fn validate_dependencies(&self) -> Result<(), ValidationError> {
+        let graph = self.iter()
+            .map(|ctx| (ctx.name.as_str(), ctx.dependencies()))
+            .collect::<HashMap<_, _>>();
+
+        for deps in graph.values() {
+            if deps.iter().any(|dep| !graph.contains_key(dep.as_str())) {
+                return Err(ValidationError::UnknownDependency);
+            }
+        }
+
+        let mut finished = HashSet::new();
+        for name in graph.keys() {
+            Self::visit(name, &graph, &mut Vec::new(), &mut finished)?;
         }
+
+        Ok(())
+    }
+
+    /// Depth-first search for dependency cycles starting at `name`.
+    fn visit<'a>(
+        name: &'a str,
+        graph: &'a HashMap<&'a str, Vec<String>>,
+        path: &mut Vec<&'a str>,
+        finished: &mut HashSet<&'a str>,
+    ) -> Result<(), ValidationError> {
+        if finished.contains(name) {
+            return Ok(());
+        }
+        if path.contains(&name) {
+            return Err(ValidationError::DependencyCycle);
+        }
+
+        path.push(name);
+        for dep in &graph[name] {
+            Self::visit(dep, graph, path, finished)?;
+        }
+        path.pop();
+        finished.insert(name);
+
+        Ok(())
     }
 }
 
 impl Context {
     pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.name.trim().is_empty() {
+            return Err(ValidationError::MissingName);
+        }
+
         if self.triggers.len() == 0 {
             return Err(ValidationError::MissingTriggers);
         }
@@ -77,6 +139,16 @@ impl Context {
 
         Ok(())
     }
+
+    /// Gets the names of the contexts this context depends upon.
+    ///
+    /// Malformed dependency configurations are ignored here, they are
+    /// reported once the context trigger is created.
+    pub fn dependencies(&self) -> Vec<String> {
+        self.triggers.get(CONTEXT_TRIGGER_NAME)
+            .and_then(|cfg| ContextTrigger::dependencies(cfg).ok())
+            .unwrap_or_default()
+    }
 }
 
 impl Default for TriggerBehavior {
@@ -85,6 +157,12 @@ impl Default for TriggerBehavior {
     }
 }
 
+/// Creates the error for an invalid configuration of the trigger or
+/// action called `name`.
+pub fn config_error<E: fmt::Display>(name: &str, err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {} configuration: {}", name, err))
+}
+
 impl fmt::Display for ValidationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.description())
@@ -94,8 +172,12 @@ impl fmt::Display for ValidationError {
 impl Error for ValidationError {
     fn description(&self) -> &'static str {
         match *self {
+            ValidationError::MissingName => "Missing context name",
+            ValidationError::DuplicateName => "Multiple contexts have the same name",
             ValidationError::MissingActions => "Missing actions to execute",
             ValidationError::MissingTriggers => "Missing action triggers",
+            ValidationError::UnknownDependency => "Depending on an unknown context",
+            ValidationError::DependencyCycle => "Contexts depend on each other in a cycle",
         }
     }
 }
@@ -192,4 +274,93 @@ mod tests {
         let cfg: Config = serde_yaml::from_str(cfg).unwrap();
         cfg.validate().unwrap();
     }
+
+    #[test]
+    fn validate_dependencies() {
+        let cfg = r#"
+          - actions:
+              command: rclone -V
+            name: Office
+            triggers:
+              wifi: Wifi
+          - actions:
+              command: rclone -V
+            name: Office and docked
+            triggers:
+              context: [Office, Docked]
+          - actions:
+              command: rclone -V
+            name: Docked
+            triggers:
+              wifi: Wifi
+        "#;
+
+        let cfg: Config = serde_yaml::from_str(cfg).unwrap();
+        cfg.validate().unwrap();
+    }
+
+    #[test]
+    fn validate_unknown_dependency() {
+        let cfg = r#"
+          actions:
+            command: rclone -V
+          name: Office and docked
+          triggers:
+            context: Office
+        "#;
+
+        let cfg: Config = serde_yaml::from_str(cfg).unwrap();
+        assert_eq!(cfg.validate(), Err(ValidationError::UnknownDependency));
+    }
+
+    #[test]
+    fn validate_dependency_cycle() {
+        let cfg = r#"
+          - actions:
+              command: rclone -V
+            name: A
+            triggers:
+              context: C
+          - actions:
+              command: rclone -V
+            name: B
+            triggers:
+              context: A
+          - actions:
+              command: rclone -V
+            name: C
+            triggers:
+              context: [B]
+        "#;
+
+        let cfg: Config = serde_yaml::from_str(cfg).unwrap();
+        assert_eq!(cfg.validate(), Err(ValidationError::DependencyCycle));
+    }
+
+    #[test]
+    fn validate_names() {
+        let unnamed = r#"
+          actions:
+            command: rclone -V
+          triggers:
+            wifi: Wifi
+        "#;
+        let cfg: Config = serde_yaml::from_str(unnamed).unwrap();
+        assert_eq!(cfg.validate(), Err(ValidationError::MissingName));
+
+        let duplicate = r#"
+          - actions:
+              command: rclone -V
+            name: Office
+            triggers:
+              wifi: Wifi
+          - actions:
+              command: rclone -V
+            name: Office
+            triggers:
+              context: Office
+        "#;
+        let cfg: Config = serde_yaml::from_str(duplicate).unwrap();
+        assert_eq!(cfg.validate(), Err(ValidationError::DuplicateName));
+    }
 }
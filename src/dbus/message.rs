@@ -0,0 +1,634 @@
+//! Encoding and decoding D-Bus messages.
+//!
+//! See https://dbus.freedesktop.org/doc/dbus-specification.html#message-protocol
+//! for the wire format. Messages are always sent in little endian, but
+//! messages in either byte order are understood.
+
+use std::io;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+/// The length of the fixed part of the message header.
+pub const HEADER_LEN: usize = 16;
+
+/// The maximum length of a message allowed by the specification.
+const MAX_MESSAGE_LEN: usize = 128 * 1024 * 1024;
+
+const PROTOCOL_VERSION: u8 = 1;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// The type of a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+}
+
+/// A value of one of the D-Bus types.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Double(f64),
+    String(String),
+    ObjectPath(String),
+    Signature(String),
+
+    /// An array, along with the signature of its elements, which is
+    /// needed for empty arrays.
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    /// Gets the signature of the value's type.
+    pub fn signature(&self) -> String {
+        match *self {
+            Value::Byte(_) => "y".to_owned(),
+            Value::Bool(_) => "b".to_owned(),
+            Value::Int16(_) => "n".to_owned(),
+            Value::UInt16(_) => "q".to_owned(),
+            Value::Int32(_) => "i".to_owned(),
+            Value::UInt32(_) => "u".to_owned(),
+            Value::Int64(_) => "x".to_owned(),
+            Value::UInt64(_) => "t".to_owned(),
+            Value::Double(_) => "d".to_owned(),
+            Value::String(_) => "s".to_owned(),
+            Value::ObjectPath(_) => "o".to_owned(),
+            Value::Signature(_) => "g".to_owned(),
+            Value::Array(ref element, _) => format!("a{}", element),
+            Value::Struct(ref fields) => {
+                let fields = fields.iter().map(Value::signature).collect::<String>();
+                format!("({})", fields)
+            },
+            Value::DictEntry(ref key, ref value) => format!("{{{}{}}}", key.signature(), value.signature()),
+            Value::Variant(_) => "v".to_owned(),
+        }
+    }
+
+    /// Gets the value of a string, object path or signature.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) | Value::ObjectPath(ref s) | Value::Signature(ref s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// A D-Bus message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub kind: Kind,
+    pub flags: u8,
+    pub serial: u32,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    /// Creates a call of the given method.
+    ///
+    /// The serial is assigned when the message is sent.
+    pub fn method_call(destination: &str, path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Message {
+            path: Some(path.to_owned()),
+            interface: Some(interface.to_owned()),
+            member: Some(member.to_owned()),
+            destination: Some(destination.to_owned()),
+            ..Self::new(Kind::MethodCall, body)
+        }
+    }
+
+    /// Creates the successful reply to the given method call.
+    ///
+    /// Replies and signals are only ever sent by the mock bus in tests.
+    #[cfg(test)]
+    pub fn method_return(call: &Message, body: Vec<Value>) -> Self {
+        Message {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            ..Self::new(Kind::MethodReturn, body)
+        }
+    }
+
+    /// Creates an error reply to the given method call.
+    #[cfg(test)]
+    pub fn error(call: &Message, name: &str, text: &str) -> Self {
+        Message {
+            error_name: Some(name.to_owned()),
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            ..Self::new(Kind::Error, vec![Value::String(text.to_owned())])
+        }
+    }
+
+    /// Creates a signal emitted by the given object.
+    #[cfg(test)]
+    pub fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Message {
+            path: Some(path.to_owned()),
+            interface: Some(interface.to_owned()),
+            member: Some(member.to_owned()),
+            ..Self::new(Kind::Signal, body)
+        }
+    }
+
+    fn new(kind: Kind, body: Vec<Value>) -> Self {
+        Message {
+            kind,
+            flags: 0,
+            serial: 0,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body,
+        }
+    }
+
+    /// Checks whether the message is the given signal.
+    pub fn is_signal(&self, interface: &str, member: &str) -> bool {
+        self.kind == Kind::Signal &&
+            self.interface.as_ref().map(String::as_str) == Some(interface) &&
+            self.member.as_ref().map(String::as_str) == Some(member)
+    }
+
+    /// Encodes the message.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+        {
+            let mut field = |code: u8, value: Value| {
+                fields.push(Value::Struct(vec![Value::Byte(code), Value::Variant(Box::new(value))]));
+            };
+            if let Some(ref path) = self.path {
+                field(FIELD_PATH, Value::ObjectPath(path.clone()));
+            }
+            if let Some(ref interface) = self.interface {
+                field(FIELD_INTERFACE, Value::String(interface.clone()));
+            }
+            if let Some(ref member) = self.member {
+                field(FIELD_MEMBER, Value::String(member.clone()));
+            }
+            if let Some(ref name) = self.error_name {
+                field(FIELD_ERROR_NAME, Value::String(name.clone()));
+            }
+            if let Some(serial) = self.reply_serial {
+                field(FIELD_REPLY_SERIAL, Value::UInt32(serial));
+            }
+            if let Some(ref destination) = self.destination {
+                field(FIELD_DESTINATION, Value::String(destination.clone()));
+            }
+            if let Some(ref sender) = self.sender {
+                field(FIELD_SENDER, Value::String(sender.clone()));
+            }
+            if !self.body.is_empty() {
+                let signature = self.body.iter().map(Value::signature).collect();
+                field(FIELD_SIGNATURE, Value::Signature(signature));
+            }
+        }
+
+        let kind = match self.kind {
+            Kind::MethodCall => 1,
+            Kind::MethodReturn => 2,
+            Kind::Error => 3,
+            Kind::Signal => 4,
+        };
+        let mut encoder = Encoder { buf: vec![b'l', kind, self.flags, PROTOCOL_VERSION, 0, 0, 0, 0] };
+        encoder.u32(self.serial);
+        encoder.value(&Value::Array("(yv)".to_owned(), fields));
+        encoder.align(8);
+
+        let body_start = encoder.buf.len();
+        for value in &self.body {
+            encoder.value(value);
+        }
+        let body_len = (encoder.buf.len() - body_start) as u32;
+        LittleEndian::write_u32(&mut encoder.buf[4..8], body_len);
+
+        encoder.buf
+    }
+
+    /// Gets the length of the whole message from its fixed header.
+    pub fn len(header: &[u8; HEADER_LEN]) -> io::Result<usize> {
+        let (body_len, fields_len) = match header[0] {
+            b'l' => (LittleEndian::read_u32(&header[4..8]), LittleEndian::read_u32(&header[12..16])),
+            b'B' => (BigEndian::read_u32(&header[4..8]), BigEndian::read_u32(&header[12..16])),
+            _ => return Err(invalid("unknown byte order")),
+        };
+
+        let len = pad(HEADER_LEN + fields_len as usize, 8) + body_len as usize;
+        if len > MAX_MESSAGE_LEN {
+            return Err(invalid("message too long"));
+        }
+        Ok(len)
+    }
+
+    /// Decodes a whole message.
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        if buf.len() < HEADER_LEN {
+            return Err(invalid("message too short"));
+        }
+
+        let mut decoder = Decoder {
+            buf,
+            pos: 0,
+            big_endian: buf[0] == b'B',
+        };
+        decoder.pos = 1;
+        let kind = match decoder.byte()? {
+            1 => Kind::MethodCall,
+            2 => Kind::MethodReturn,
+            3 => Kind::Error,
+            4 => Kind::Signal,
+            _ => return Err(invalid("unknown message type")),
+        };
+        let flags = decoder.byte()?;
+        if decoder.byte()? != PROTOCOL_VERSION {
+            return Err(invalid("unsupported protocol version"));
+        }
+        let body_len = decoder.u32()? as usize;
+
+        let mut msg = Message::new(kind, Vec::new());
+        msg.flags = flags;
+        msg.serial = decoder.u32()?;
+
+        let mut signature = String::new();
+        let fields = match decoder.value("a(yv)")? {
+            Value::Array(_, fields) => fields,
+            _ => unreachable!(),
+        };
+        for field in fields {
+            let (code, value) = match field {
+                Value::Struct(mut pair) => match (pair.pop(), pair.pop()) {
+                    (Some(Value::Variant(value)), Some(Value::Byte(code))) => (code, *value),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+
+            match (code, value) {
+                (FIELD_PATH, Value::ObjectPath(path)) => msg.path = Some(path),
+                (FIELD_INTERFACE, Value::String(interface)) => msg.interface = Some(interface),
+                (FIELD_MEMBER, Value::String(member)) => msg.member = Some(member),
+                (FIELD_ERROR_NAME, Value::String(name)) => msg.error_name = Some(name),
+                (FIELD_REPLY_SERIAL, Value::UInt32(serial)) => msg.reply_serial = Some(serial),
+                (FIELD_DESTINATION, Value::String(destination)) => msg.destination = Some(destination),
+                (FIELD_SENDER, Value::String(sender)) => msg.sender = Some(sender),
+                (FIELD_SIGNATURE, Value::Signature(sig)) => signature = sig,
+                (FIELD_PATH, _) | (FIELD_INTERFACE, _) | (FIELD_MEMBER, _) | (FIELD_ERROR_NAME, _) |
+                (FIELD_REPLY_SERIAL, _) | (FIELD_DESTINATION, _) | (FIELD_SENDER, _) | (FIELD_SIGNATURE, _) => {
+                    return Err(invalid("header field of wrong type"));
+                },
+                // Unknown fields must be ignored.
+                _ => {},
+            }
+        }
+
+        decoder.align(8)?;
+        let body_end = decoder.pos + body_len;
+        if body_end > buf.len() {
+            return Err(invalid("body too short"));
+        }
+        decoder.buf = &buf[..body_end];
+
+        let mut rest = &signature[..];
+        while !rest.is_empty() {
+            let (ty, tail) = split_type(rest)?;
+            msg.body.push(decoder.value(ty)?);
+            rest = tail;
+        }
+
+        Ok(msg)
+    }
+}
+
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn align(&mut self, alignment: usize) {
+        let len = pad(self.buf.len(), alignment);
+        self.buf.resize(len, 0);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.align(4);
+        let mut bytes = [0; 4];
+        LittleEndian::write_u32(&mut bytes, v);
+        self.buf.extend_from_slice(&bytes);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn value(&mut self, value: &Value) {
+        match *value {
+            Value::Byte(v) => self.buf.push(v),
+            Value::Bool(v) => self.u32(v as u32),
+            Value::Int16(v) => self.value(&Value::UInt16(v as u16)),
+            Value::UInt16(v) => {
+                self.align(2);
+                let mut bytes = [0; 2];
+                LittleEndian::write_u16(&mut bytes, v);
+                self.buf.extend_from_slice(&bytes);
+            },
+            Value::Int32(v) => self.u32(v as u32),
+            Value::UInt32(v) => self.u32(v),
+            Value::Int64(v) => self.value(&Value::UInt64(v as u64)),
+            Value::UInt64(v) => {
+                self.align(8);
+                let mut bytes = [0; 8];
+                LittleEndian::write_u64(&mut bytes, v);
+                self.buf.extend_from_slice(&bytes);
+            },
+            Value::Double(v) => self.value(&Value::UInt64(v.to_bits())),
+            Value::String(ref s) | Value::ObjectPath(ref s) => self.string(s),
+            Value::Signature(ref s) => {
+                self.buf.push(s.len() as u8);
+                self.buf.extend_from_slice(s.as_bytes());
+                self.buf.push(0);
+            },
+            Value::Array(ref element, ref items) => {
+                self.u32(0);
+                let len_pos = self.buf.len() - 4;
+
+                // The padding before the first element doesn't count
+                // towards the length, even if there are no elements.
+                self.align(alignment(element));
+                let start = self.buf.len();
+                for item in items {
+                    self.value(item);
+                }
+                let len = (self.buf.len() - start) as u32;
+                LittleEndian::write_u32(&mut self.buf[len_pos..len_pos + 4], len);
+            },
+            Value::Struct(ref fields) => {
+                self.align(8);
+                for field in fields {
+                    self.value(field);
+                }
+            },
+            Value::DictEntry(ref key, ref value) => {
+                self.align(8);
+                self.value(key);
+                self.value(value);
+            },
+            Value::Variant(ref value) => {
+                self.value(&Value::Signature(value.signature()));
+                self.value(value);
+            },
+        }
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Decoder<'a> {
+    fn align(&mut self, alignment: usize) -> io::Result<()> {
+        self.take(pad(self.pos, alignment) - self.pos).map(|_| ())
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.pos + len > self.buf.len() {
+            return Err(invalid("message too short"));
+        }
+
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.align(2)?;
+        let bytes = self.take(2)?;
+        Ok(if self.big_endian { BigEndian::read_u16(bytes) } else { LittleEndian::read_u16(bytes) })
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.align(4)?;
+        let bytes = self.take(4)?;
+        Ok(if self.big_endian { BigEndian::read_u32(bytes) } else { LittleEndian::read_u32(bytes) })
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        self.align(8)?;
+        let bytes = self.take(8)?;
+        Ok(if self.big_endian { BigEndian::read_u64(bytes) } else { LittleEndian::read_u64(bytes) })
+    }
+
+    fn string(&mut self, len: usize) -> io::Result<String> {
+        let bytes = self.take(len + 1)?;
+        if bytes[len] != 0 {
+            return Err(invalid("string not terminated"));
+        }
+
+        String::from_utf8(bytes[..len].to_vec()).map_err(|_| invalid("string not valid UTF-8"))
+    }
+
+    /// Decodes a value of the given single complete type.
+    fn value(&mut self, ty: &str) -> io::Result<Value> {
+        let value = match ty.as_bytes()[0] {
+            b'y' => Value::Byte(self.byte()?),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => Value::Int16(self.u16()? as i16),
+            b'q' => Value::UInt16(self.u16()?),
+            b'i' => Value::Int32(self.u32()? as i32),
+            b'u' => Value::UInt32(self.u32()?),
+            b'x' => Value::Int64(self.u64()? as i64),
+            b't' => Value::UInt64(self.u64()?),
+            b'd' => Value::Double(f64::from_bits(self.u64()?)),
+            b's' => {
+                let len = self.u32()? as usize;
+                Value::String(self.string(len)?)
+            },
+            b'o' => {
+                let len = self.u32()? as usize;
+                Value::ObjectPath(self.string(len)?)
+            },
+            b'g' => {
+                let len = self.byte()? as usize;
+                Value::Signature(self.string(len)?)
+            },
+            b'a' => {
+                let element = &ty[1..];
+                let len = self.u32()? as usize;
+                self.align(alignment(element))?;
+
+                let end = self.pos + len;
+                if end > self.buf.len() {
+                    return Err(invalid("array too long"));
+                }
+                let mut items = Vec::new();
+                while self.pos < end {
+                    items.push(self.value(element)?);
+                }
+                Value::Array(element.to_owned(), items)
+            },
+            b'(' => {
+                self.align(8)?;
+                let mut rest = &ty[1..ty.len() - 1];
+                let mut fields = Vec::new();
+                while !rest.is_empty() {
+                    let (field, tail) = split_type(rest)?;
+                    fields.push(self.value(field)?);
+                    rest = tail;
+                }
+                Value::Struct(fields)
+            },
+            b'{' => {
+                self.align(8)?;
+                let (key, rest) = split_type(&ty[1..ty.len() - 1])?;
+                let (value, _) = split_type(rest)?;
+                let key = self.value(key)?;
+                Value::DictEntry(Box::new(key), Box::new(self.value(value)?))
+            },
+            b'v' => {
+                let len = self.byte()? as usize;
+                let signature = self.string(len)?;
+                let (inner, rest) = split_type(&signature)?;
+                if !rest.is_empty() {
+                    return Err(invalid("variant of more than one type"));
+                }
+                Value::Variant(Box::new(self.value(inner)?))
+            },
+            _ => return Err(invalid("unknown type in signature")),
+        };
+
+        Ok(value)
+    }
+}
+
+/// Splits the first single complete type off the given signature.
+fn split_type(signature: &str) -> io::Result<(&str, &str)> {
+    let bytes = signature.as_bytes();
+    let mut depth = 0;
+
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'a' => continue,
+            b'(' | b'{' => depth += 1,
+            b')' | b'}' if depth == 0 => return Err(invalid("unbalanced signature")),
+            b')' | b'}' => depth -= 1,
+            _ => {},
+        }
+        if depth == 0 {
+            return Ok(signature.split_at(i + 1));
+        }
+    }
+
+    Err(invalid("incomplete signature"))
+}
+
+/// Gets the alignment of values of the given type.
+fn alignment(ty: &str) -> usize {
+    match ty.as_bytes().first() {
+        Some(&b'y') | Some(&b'g') | Some(&b'v') => 1,
+        Some(&b'n') | Some(&b'q') => 2,
+        Some(&b'x') | Some(&b't') | Some(&b'd') | Some(&b'(') | Some(&b'{') => 8,
+        _ => 4,
+    }
+}
+
+fn pad(pos: usize, alignment: usize) -> usize {
+    (pos + alignment - 1) / alignment * alignment
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid D-Bus message: {}.", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_messages() {
+        let hints = vec![
+            Value::DictEntry(
+                Box::new(Value::String("urgency".to_owned())),
+                Box::new(Value::Variant(Box::new(Value::Byte(2)))),
+            ),
+        ];
+        let mut msg = Message::method_call(
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "Notify",
+            vec![
+                Value::String("runtext".to_owned()),
+                Value::UInt32(0),
+                Value::Array("s".to_owned(), vec![]),
+                Value::Array("{sv}".to_owned(), hints),
+                Value::Struct(vec![Value::Int64(-1), Value::Double(0.5), Value::Bool(true)]),
+            ],
+        );
+        msg.serial = 7;
+
+        let bytes = msg.encode();
+        let mut header = [0; HEADER_LEN];
+        header.copy_from_slice(&bytes[..HEADER_LEN]);
+        assert_eq!(Message::len(&header).unwrap(), bytes.len());
+        assert_eq!(Message::decode(&bytes).unwrap(), msg);
+    }
+
+    #[test]
+    fn decodes_big_endian_messages() {
+        // A reply to serial 1 carrying the string "a", sent by a big
+        // endian peer.
+        let bytes = b"B\x02\x00\x01\x00\x00\x00\x06\x00\x00\x00\x02\x00\x00\x00\x0f\
+                      \x05\x01u\x00\x00\x00\x00\x01\x08\x01g\x00\x01s\x00\x00\
+                      \x00\x00\x00\x01a\x00";
+        let msg = Message::decode(bytes).unwrap();
+
+        assert_eq!(msg.kind, Kind::MethodReturn);
+        assert_eq!(msg.reply_serial, Some(1));
+        assert_eq!(msg.body, vec![Value::String("a".to_owned())]);
+    }
+
+    #[test]
+    fn splits_signatures() {
+        assert_eq!(split_type("a{sv}u").unwrap(), ("a{sv}", "u"));
+        assert_eq!(split_type("(ua(yv))s").unwrap(), ("(ua(yv))", "s"));
+        assert!(split_type("a").is_err());
+        assert!(split_type(")").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! A mock message bus for testing code talking to D-Bus services.
+//!
+//! The bus listens on a private socket and answers every method call
+//! through a handler standing in for the services, except for `Hello`,
+//! which it answers itself.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::message::HEADER_LEN;
+use super::{Kind, Message, Value};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A running mock bus, which is shut down when dropped.
+#[derive(Debug)]
+pub struct MockBus {
+    address: String,
+    path: PathBuf,
+}
+
+impl MockBus {
+    /// Starts a bus that answers method calls with the messages returned
+    /// by `handler`, e.g. a reply followed by signals.
+    pub fn start<F>(handler: F) -> Self
+        where F: FnMut(&Message) -> Vec<Message> + Send + 'static {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("runtext-dbus-{}-{}", process::id(), id));
+        let _ = fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let handler = Arc::new(Mutex::new(handler));
+        thread::spawn(move || {
+            for (client, stream) in listener.incoming().enumerate() {
+                let handler = handler.clone();
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+                thread::spawn(move || {
+                    let _ = serve(stream, client, |call| (*handler.lock().unwrap())(call));
+                });
+            }
+        });
+
+        MockBus {
+            address: format!("unix:path={}", path.display()),
+            path,
+        }
+    }
+
+    /// Gets the address to connect to the bus.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+impl Drop for MockBus {
+    fn drop(&mut self) {
+        // The listening thread stays around, but nobody can connect anymore.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn serve<F>(stream: UnixStream, client: usize, mut handler: F) -> io::Result<()>
+    where F: FnMut(&Message) -> Vec<Message> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let mut nul = [0; 1];
+    reader.read_exact(&mut nul)?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        if line.starts_with("AUTH EXTERNAL") {
+            writer.write_all(b"OK 0123456789abcdef0123456789abcdef\r\n")?;
+        } else if line.starts_with("BEGIN") {
+            break;
+        } else {
+            writer.write_all(b"ERROR\r\n")?;
+        }
+    }
+
+    let name = format!(":1.{}", client);
+    let mut serial = 0;
+    loop {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let mut buf = header.to_vec();
+        buf.resize(Message::len(&header)?, 0);
+        reader.read_exact(&mut buf[HEADER_LEN..])?;
+
+        let mut call = Message::decode(&buf)?;
+        if call.kind != Kind::MethodCall {
+            continue;
+        }
+        call.sender = Some(name.clone());
+
+        let replies = if call.member.as_ref().map(String::as_str) == Some("Hello") {
+            vec![Message::method_return(&call, vec![Value::String(name.clone())])]
+        } else {
+            handler(&call)
+        };
+        for mut reply in replies {
+            serial += 1;
+            reply.serial = serial;
+            writer.write_all(&reply.encode())?;
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! A minimal asynchronous D-Bus client.
+//!
+//! It supports just enough of the protocol for actions to call methods
+//! of services on the session and system bus and to listen for their
+//! signals: connecting via Unix sockets, `EXTERNAL` authentication and
+//! messages without file descriptors.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+
+use futures::future::{self, Loop};
+use futures::prelude::*;
+use futures::stream;
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::unsync::oneshot;
+use libc;
+use tokio_core::reactor::Handle;
+use tokio_io::AsyncRead;
+use tokio_io::io::{read_exact, write_all};
+use tokio_uds::UnixStream;
+
+pub use self::message::{Kind, Message, Value};
+
+use self::message::HEADER_LEN;
+
+mod message;
+#[cfg(test)]
+pub mod mock;
+
+const BUS_NAME: &'static str = "org.freedesktop.DBus";
+const BUS_PATH: &'static str = "/org/freedesktop/DBus";
+
+/// The well-known address of the system bus.
+const DEFAULT_SYSTEM_ADDRESS: &'static str = "unix:path=/var/run/dbus/system_bus_socket";
+
+/// The longest line accepted during authentication.
+const MAX_AUTH_LINE: usize = 512;
+
+/// Gets the address of the user's session bus.
+pub fn session_address() -> io::Result<String> {
+    if let Ok(address) = env::var("DBUS_SESSION_BUS_ADDRESS") {
+        return Ok(address);
+    }
+
+    // Sessions managed by systemd have the bus at a fixed location.
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => Ok(format!("unix:path={}", PathBuf::from(dir).join("bus").display())),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "No D-Bus session bus found.")),
+    }
+}
+
+/// Gets the address of the system bus.
+pub fn system_address() -> String {
+    env::var("DBUS_SYSTEM_BUS_ADDRESS").unwrap_or_else(|_| DEFAULT_SYSTEM_ADDRESS.to_owned())
+}
+
+/// A connection to a message bus.
+///
+/// The connection is cheap to clone, all clones refer to the same
+/// connection, which is closed once the last of them is dropped.
+#[derive(Clone, Debug)]
+pub struct Connection(Rc<RefCell<Inner>>);
+
+#[derive(Debug)]
+struct Inner {
+    serial: u32,
+    writer: UnboundedSender<Vec<u8>>,
+    replies: HashMap<u32, oneshot::Sender<Message>>,
+    signals: Vec<UnboundedSender<Message>>,
+    closed: bool,
+    _close: oneshot::Sender<()>,
+}
+
+impl Connection {
+    /// Connects to the bus at the given address and registers with it.
+    pub fn open(address: &str, handle: &Handle) -> Box<Future<Item = Self, Error = io::Error>> {
+        let h = handle.clone();
+        let fut = future::result(parse_address(address))
+            .and_then(move |path| {
+                let stream = StdUnixStream::connect(&path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("Failed to connect to D-Bus at {}: {}", path.display(), e)))?;
+                UnixStream::from_std(stream, h.new_tokio_handle())
+                    .map(|stream| (stream, h))
+            })
+            .and_then(|(stream, h)| authenticate(stream).map(move |stream| (stream, h)))
+            .and_then(|(stream, h)| {
+                let conn = Self::start(stream, &h);
+                let hello = Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "Hello", vec![]);
+                conn.call(hello).map(move |_| conn)
+            });
+
+        Box::new(fut)
+    }
+
+    /// Connects to the user's session bus.
+    pub fn session(handle: &Handle) -> Box<Future<Item = Self, Error = io::Error>> {
+        match session_address() {
+            Ok(address) => Self::open(&address, handle),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    /// Connects to the system bus.
+    pub fn system(handle: &Handle) -> Box<Future<Item = Self, Error = io::Error>> {
+        Self::open(&system_address(), handle)
+    }
+
+    /// Starts exchanging messages over the authenticated stream in the
+    /// background.
+    fn start(stream: UnixStream, handle: &Handle) -> Self {
+        let (reader, writer) = stream.split();
+        let (write_tx, write_rx) = mpsc::unbounded::<Vec<u8>>();
+        let (close_tx, close_rx) = oneshot::channel();
+
+        let conn = Connection(Rc::new(RefCell::new(Inner {
+            serial: 0,
+            writer: write_tx,
+            replies: HashMap::new(),
+            signals: Vec::new(),
+            closed: false,
+            _close: close_tx,
+        })));
+
+        // Writing ends once the last connection is dropped.
+        let write = write_rx
+            .fold(writer, |writer, msg| write_all(writer, msg).map(|(writer, _)| writer).map_err(|_| ()))
+            .then(|_| Ok(()));
+        handle.spawn(write);
+
+        // Calls still waiting for their reply fail once the bus goes away.
+        let inner = Rc::downgrade(&conn.0);
+        let closed = inner.clone();
+        let read = stream::unfold(reader, |reader| Some(read_message(reader)))
+            .for_each(move |msg| {
+                dispatch(&inner, msg);
+                Ok(())
+            })
+            .then(move |res| {
+                if let Some(inner) = closed.upgrade() {
+                    let mut inner = inner.borrow_mut();
+                    inner.closed = true;
+                    inner.replies.clear();
+                }
+                res.map_err(|err| eprintln!("Failed to read from D-Bus: {}.", err))
+            })
+            .select(close_rx.then(|_| Ok(())))
+            .then(|_| Ok(()));
+        handle.spawn(read);
+
+        conn
+    }
+
+    /// Calls a method and waits for its reply.
+    ///
+    /// Error replies are turned into errors carrying the error's name
+    /// and message.
+    pub fn call(&self, mut msg: Message) -> Box<Future<Item = Message, Error = io::Error>> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut inner = self.0.borrow_mut();
+            inner.serial += 1;
+            msg.serial = inner.serial;
+
+            // Dropping the sender right away fails the call below.
+            if !inner.closed && inner.writer.unbounded_send(msg.encode()).is_ok() {
+                inner.replies.insert(msg.serial, tx);
+            }
+        }
+
+        // The connection is kept open until the reply arrives.
+        let conn = self.clone();
+        let method = msg.member.unwrap_or_default();
+        let fut = rx
+            .map_err(|_| io::Error::new(io::ErrorKind::ConnectionAborted, "D-Bus connection closed."))
+            .and_then(move |reply| {
+                drop(conn);
+                if reply.kind != Kind::Error {
+                    return Ok(reply);
+                }
+
+                let name = reply.error_name.unwrap_or_default();
+                let text = reply.body.first().and_then(Value::as_str).unwrap_or("").to_owned();
+                Err(io::Error::new(io::ErrorKind::Other, format!("{} failed: {}: {}", method, name, text)))
+            });
+        Box::new(fut)
+    }
+
+    /// Subscribes to the signals matching the given rule, e.g.
+    /// `type='signal',interface='org.example.Foo'`.
+    ///
+    /// The stream yields all signals received on the connection, not just
+    /// the ones matching the rule, so they still need to be filtered.
+    pub fn signals(&self, rule: &str) -> Box<Future<Item = UnboundedReceiver<Message>, Error = io::Error>> {
+        let (tx, rx) = mpsc::unbounded();
+        self.0.borrow_mut().signals.push(tx);
+
+        let add_match = Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "AddMatch", vec![Value::String(rule.to_owned())]);
+        Box::new(self.call(add_match).map(|_| rx))
+    }
+}
+
+/// Hands a received message to whoever waits for it.
+fn dispatch(inner: &Weak<RefCell<Inner>>, msg: Message) {
+    let inner = match inner.upgrade() {
+        Some(inner) => inner,
+        None => return,
+    };
+    let mut inner = inner.borrow_mut();
+
+    match msg.kind {
+        Kind::MethodReturn | Kind::Error => {
+            let tx = msg.reply_serial.and_then(|serial| inner.replies.remove(&serial));
+            if let Some(tx) = tx {
+                let _ = tx.send(msg);
+            }
+        },
+        Kind::Signal => inner.signals.retain(|tx| tx.unbounded_send(msg.clone()).is_ok()),
+        // Nothing is exported, calls by other peers go unanswered.
+        Kind::MethodCall => {},
+    }
+}
+
+/// Reads the next message.
+fn read_message<R: AsyncRead + 'static>(reader: R) -> Box<Future<Item = (Message, R), Error = io::Error>> {
+    let fut = read_exact(reader, [0; HEADER_LEN])
+        .and_then(|(reader, header)| {
+            let len = Message::len(&header)?;
+            Ok((reader, header, len))
+        })
+        .and_then(|(reader, header, len)| {
+            read_exact(reader, vec![0; len - HEADER_LEN]).and_then(move |(reader, rest)| {
+                let mut buf = header.to_vec();
+                buf.extend(rest);
+                Ok((Message::decode(&buf)?, reader))
+            })
+        });
+
+    Box::new(fut)
+}
+
+/// Authenticates as the current user, using the credentials passed along
+/// with the socket.
+fn authenticate(stream: UnixStream) -> Box<Future<Item = UnixStream, Error = io::Error>> {
+    let uid = unsafe { libc::getuid() }.to_string();
+    let hex = uid.bytes().map(|b| format!("{:02x}", b)).collect::<String>();
+    let auth = format!("\0AUTH EXTERNAL {}\r\n", hex);
+
+    let fut = write_all(stream, auth.into_bytes())
+        .and_then(|(stream, _)| read_line(stream))
+        .and_then(|(stream, line)| {
+            if !line.starts_with("OK ") {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("D-Bus authentication failed: {}", line.trim()),
+                ));
+            }
+            Ok(stream)
+        })
+        .and_then(|stream| write_all(stream, b"BEGIN\r\n"))
+        .map(|(stream, _)| stream);
+
+    Box::new(fut)
+}
+
+/// Reads a line of the authentication protocol.
+///
+/// Lines are read byte by byte, as the messages following the
+/// authentication must not end up in a buffer.
+fn read_line(stream: UnixStream) -> Box<Future<Item = (UnixStream, String), Error = io::Error>> {
+    let fut = future::loop_fn((stream, Vec::new()), |(stream, mut line)| {
+        read_exact(stream, [0; 1]).and_then(move |(stream, byte)| {
+            line.push(byte[0]);
+            if line.ends_with(b"\r\n") {
+                Ok(Loop::Break((stream, String::from_utf8_lossy(&line).into_owned())))
+            } else if line.len() > MAX_AUTH_LINE {
+                Err(io::Error::new(io::ErrorKind::InvalidData, "D-Bus authentication line too long."))
+            } else {
+                Ok(Loop::Continue((stream, line)))
+            }
+        })
+    });
+
+    Box::new(fut)
+}
+
+/// Finds the socket to connect to in a bus address like
+/// `unix:path=/run/user/1000/bus,guid=...`.
+///
+/// Addresses may list several alternatives separated by `;`, the first
+/// supported one is used.
+fn parse_address(address: &str) -> io::Result<PathBuf> {
+    for entry in address.split(';') {
+        let mut parts = entry.splitn(2, ':');
+        if parts.next() != Some("unix") {
+            continue;
+        }
+
+        let path = parts.next()
+            .unwrap_or("")
+            .split(',')
+            .filter_map(|pair| {
+                let mut kv = pair.splitn(2, '=');
+                match (kv.next(), kv.next()) {
+                    (Some("path"), Some(value)) => Some(unescape(value)),
+                    _ => None,
+                }
+            })
+            .next();
+        if let Some(path) = path {
+            return path.map(PathBuf::from);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Unsupported D-Bus address '{}', only unix:path= addresses are supported.", address),
+    ))
+}
+
+/// Decodes the `%xx` escapes of an address value.
+fn unescape(value: &str) -> io::Result<String> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid escape in D-Bus address '{}'.", value));
+
+    let mut bytes = Vec::new();
+    let mut iter = value.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+
+        let hex = [iter.next().ok_or_else(invalid)?, iter.next().ok_or_else(invalid)?];
+        let hex = ::std::str::from_utf8(&hex).map_err(|_| invalid())?;
+        bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+    }
+
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_core::reactor::Core;
+
+    use super::*;
+    use self::mock::MockBus;
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(
+            parse_address("unix:path=/run/user/1000/bus,guid=1234").unwrap(),
+            PathBuf::from("/run/user/1000/bus"),
+        );
+        assert_eq!(
+            parse_address("unix:abstract=/tmp/dbus-x;unix:path=/tmp/with%20space").unwrap(),
+            PathBuf::from("/tmp/with space"),
+        );
+        assert!(parse_address("tcp:host=localhost,port=1234").is_err());
+        assert!(parse_address("unix:path=/tmp/%zz").is_err());
+    }
+
+    #[test]
+    fn calls_methods() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let bus = MockBus::start(|call| match call.member.as_ref().map(String::as_str) {
+            Some("Echo") => vec![Message::method_return(call, call.body.clone())],
+            _ => vec![Message::error(call, "org.example.Error", "Unknown method")],
+        });
+
+        let conn = core.run(Connection::open(bus.address(), &handle)).unwrap();
+        let echo = Message::method_call("org.example", "/", "org.example", "Echo", vec![Value::UInt32(42)]);
+        let reply = core.run(conn.call(echo)).unwrap();
+        assert_eq!(reply.body, vec![Value::UInt32(42)]);
+
+        let fail = Message::method_call("org.example", "/", "org.example", "Fail", vec![]);
+        let err = core.run(conn.call(fail)).unwrap_err();
+        assert!(err.to_string().contains("org.example.Error: Unknown method"));
+    }
+
+    #[test]
+    fn receives_signals() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let bus = MockBus::start(|call| vec![
+            Message::method_return(call, vec![]),
+            Message::signal("/", "org.example", "Called", vec![Value::String(call.member.clone().unwrap())]),
+        ]);
+
+        let conn = core.run(Connection::open(bus.address(), &handle)).unwrap();
+        let signals = core.run(conn.signals("type='signal'")).unwrap();
+        let (signal, _) = core.run(signals.into_future()).map_err(|_| ()).unwrap();
+
+        let signal = signal.unwrap();
+        assert!(signal.is_signal("org.example", "Called"));
+        assert_eq!(signal.body, vec![Value::String("AddMatch".to_owned())]);
+    }
+}
@@ -6,66 +6,136 @@ use futures_stream_select_all::select_all;
 use serde_yaml::Value;
 use tokio_core::reactor::Handle;
 
-use actions::Action;
+use actions::{Action, Event};
 use actions::command::{ACTION_NAME as COMMAND_ACTION_NAME, CommandAction};
+use actions::file::{ACTION_NAME as FILE_ACTION_NAME, FileAction};
+#[cfg(unix)]
+use actions::notify::{ACTION_NAME as NOTIFY_ACTION_NAME, NotifyAction};
+#[cfg(unix)]
+use actions::systemd::{ACTION_NAME as SYSTEMD_ACTION_NAME, SystemdAction};
+use bus::Bus;
+#[cfg(unix)]
+use shells::Shells;
 use context::{Context, TriggerBehavior};
 use triggers::{Activity, Trigger};
+#[cfg(unix)]
+use triggers::audio::{TRIGGER_NAME as AUDIO_TRIGGER_NAME, AudioTrigger};
+use triggers::calendar::{TRIGGER_NAME as CALENDAR_TRIGGER_NAME, CalendarTrigger};
+use triggers::context::{TRIGGER_NAME as CONTEXT_TRIGGER_NAME, ContextTrigger};
+#[cfg(unix)]
+use triggers::cwd::{TRIGGER_NAME as CWD_TRIGGER_NAME, CwdTrigger};
+#[cfg(target_os = "linux")]
+use triggers::ethernet::{TRIGGER_NAME as ETHERNET_TRIGGER_NAME, EthernetTrigger};
+#[cfg(unix)]
+use triggers::host::{TRIGGER_NAME as HOST_TRIGGER_NAME, HostTrigger};
+#[cfg(target_os = "linux")]
+use triggers::input::{TRIGGER_NAME as INPUT_TRIGGER_NAME, InputTrigger};
+#[cfg(unix)]
+use triggers::media::{TRIGGER_NAME as MEDIA_TRIGGER_NAME, MediaTrigger};
+#[cfg(unix)]
+use triggers::signal::{TRIGGER_NAME as SIGNAL_TRIGGER_NAME, SignalTrigger};
+use triggers::sun::{TRIGGER_NAME as SUN_TRIGGER_NAME, SunTrigger};
+#[cfg(target_os = "linux")]
+use triggers::timezone::{TRIGGER_NAME as TIMEZONE_TRIGGER_NAME, TimezoneTrigger};
+#[cfg(target_os = "linux")]
+use triggers::users::{TRIGGER_NAME as USERS_TRIGGER_NAME, UsersTrigger};
 use triggers::wifi::{TRIGGER_NAME as WIFI_TRIGGER_NAME, WifiTrigger};
+use triggers::wifi_scan::{TRIGGER_NAME as WIFI_SCAN_TRIGGER_NAME, WifiScanTrigger};
+#[cfg(unix)]
+use triggers::window::{TRIGGER_NAME as WINDOW_TRIGGER_NAME, WindowTrigger};
+
+/// The state shared between the drivers of all contexts.
+#[derive(Clone, Debug)]
+pub struct Sources {
+    /// Every change of a context's activity is published on the bus.
+    pub bus: Bus,
+    /// The working directories reported by shells.
+    #[cfg(unix)]
+    pub shells: Shells,
+}
+
+impl Sources {
+    pub fn new() -> Self {
+        Sources {
+            bus: Bus::new(),
+            #[cfg(unix)]
+            shells: Shells::new(),
+        }
+    }
+}
 
 /// Drives the given context listening for evidence sources and
 /// executing actions as required.
-pub fn drive(ctx: Context, handle: Handle) -> io::Result<Box<Future<Item = (), Error = ()>>> {
+pub fn drive(ctx: Context, handle: Handle, sources: Sources) -> io::Result<Box<Future<Item = (), Error = ()>>> {
     let mut actions = ctx.actions.iter()
-        .map(|(key, config)| get_action(key, config))
-        .collect::<io::Result<Vec<Box<Action>>>>()?;
+        .map(|(key, config)| get_action(key, config, &handle).map(|a| (key.clone(), a)))
+        .collect::<io::Result<Vec<(String, Box<Action>)>>>()?;
 
     let h = handle.clone();
     let triggers = ctx.triggers.iter()
-        .map(|(key, config)| get_trigger(key, config))
-        .collect::<io::Result<Vec<Box<Trigger>>>>()?
+        .map(|(key, config)| get_trigger(key, config, &sources).map(|t| (key.clone(), t)))
+        .collect::<io::Result<Vec<(String, Box<Trigger>)>>>()?
         .into_iter()
-        .map(|mut t| t.listen(h.clone()));
+        .map(|(name, mut t)| t.listen(h.clone()).map(move |act| (name.clone(), act)));
 
     let mut activity_counter = 0;
+    let mut was_active = false;
     let driver = select_all(triggers)
-        .for_each(move |act| {
-            let prev_act_counter = activity_counter;
+        .for_each(move |(trigger, act)| {
             match act {
                 Activity::Active => activity_counter += 1,
                 Activity::Inactive => activity_counter -= 1,
             }
 
-            let is_all_active_and = ctx.trigger_behavior == TriggerBehavior::And &&
-                activity_counter == ctx.triggers.len();
-            let is_or_and_has_active = ctx.trigger_behavior == TriggerBehavior::Or &&
-                activity_counter > 0 && prev_act_counter == 0;
-
-            if is_all_active_and || is_or_and_has_active {
-                let enter_all = actions.iter_mut()
-                    .map(|act| act.enter())
-                    .collect::<Vec<_>>();
-                let fut = future::join_all(enter_all)
-                    .map(|_| ());
-
-                Box::new(fut) as Box<Future<Item = (), Error = io::Error>>
-            } else {
-                let leave_all = actions.iter_mut()
-                    .map(|act| act.leave())
-                    .collect::<Vec<_>>();
-                let fut = future::join_all(leave_all)
-                    .map(|_| ());
-
-                Box::new(fut) as Box<Future<Item = (), Error = io::Error>>
+            let is_active = match ctx.trigger_behavior {
+                TriggerBehavior::And => activity_counter == ctx.triggers.len(),
+                TriggerBehavior::Or => activity_counter > 0,
+            };
+            if is_active == was_active {
+                return Box::new(future::ok(())) as Box<Future<Item = (), Error = io::Error>>;
             }
+
+            was_active = is_active;
+            sources.bus.publish(&ctx.name, if is_active { Activity::Active } else { Activity::Inactive });
+
+            let event = Event {
+                context: ctx.name.clone(),
+                trigger,
+            };
+
+            // A failing action must neither keep the others from completing
+            // nor stop the context from being driven.
+            let transition = if is_active { "entering" } else { "leaving" };
+            let all = actions.iter_mut()
+                .map(|&mut (ref name, ref mut act)| {
+                    let fut = if is_active { act.enter(&event) } else { act.leave(&event) };
+                    let name = name.clone();
+                    let context = event.context.clone();
+
+                    fut.then(move |res| {
+                        if let Err(err) = res {
+                            eprintln!("Action '{}' failed while {} context '{}': {}", name, transition, context, err);
+                        }
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            Box::new(future::join_all(all).map(|_| ())) as Box<Future<Item = (), Error = io::Error>>
         })
         .map_err(|err| eprintln!("Experienced error while driving context: {:?}.", err));
 
     Ok(Box::new(driver))
 }
 
-fn get_action(name: &str, config: &Value) -> io::Result<Box<Action>> {
+fn get_action(name: &str, config: &Value, handle: &Handle) -> io::Result<Box<Action>> {
     match name.trim() {
-        COMMAND_ACTION_NAME => Ok(Box::new(CommandAction::from_config(config)?)),
+        COMMAND_ACTION_NAME => Ok(Box::new(CommandAction::from_config(config, handle.clone())?)),
+        FILE_ACTION_NAME => Ok(Box::new(FileAction::from_config(config)?)),
+        #[cfg(unix)]
+        NOTIFY_ACTION_NAME => Ok(Box::new(NotifyAction::from_config(config, handle.clone())?)),
+        #[cfg(unix)]
+        SYSTEMD_ACTION_NAME => Ok(Box::new(SystemdAction::from_config(config, handle.clone())?)),
 
         _ => Err(io::Error::new(
             io::ErrorKind::InvalidData,
@@ -74,9 +144,33 @@ fn get_action(name: &str, config: &Value) -> io::Result<Box<Action>> {
     }
 }
 
-fn get_trigger(name: &str, config: &Value) -> io::Result<Box<Trigger>> {
+fn get_trigger(name: &str, config: &Value, sources: &Sources) -> io::Result<Box<Trigger>> {
     match name.trim() {
+        #[cfg(unix)]
+        AUDIO_TRIGGER_NAME => Ok(Box::new(AudioTrigger::from_config(config)?)),
+        CALENDAR_TRIGGER_NAME => Ok(Box::new(CalendarTrigger::from_config(config)?)),
+        CONTEXT_TRIGGER_NAME => Ok(Box::new(ContextTrigger::from_config(config, sources.bus.clone())?)),
+        #[cfg(unix)]
+        CWD_TRIGGER_NAME => Ok(Box::new(CwdTrigger::from_config(config, sources.shells.clone())?)),
+        #[cfg(target_os = "linux")]
+        ETHERNET_TRIGGER_NAME => Ok(Box::new(EthernetTrigger::from_config(config)?)),
+        #[cfg(unix)]
+        HOST_TRIGGER_NAME => Ok(Box::new(HostTrigger::from_config(config)?)),
+        #[cfg(target_os = "linux")]
+        INPUT_TRIGGER_NAME => Ok(Box::new(InputTrigger::from_config(config)?)),
+        #[cfg(unix)]
+        MEDIA_TRIGGER_NAME => Ok(Box::new(MediaTrigger::from_config(config)?)),
+        #[cfg(unix)]
+        SIGNAL_TRIGGER_NAME => Ok(Box::new(SignalTrigger::from_config(config)?)),
+        SUN_TRIGGER_NAME => Ok(Box::new(SunTrigger::from_config(config)?)),
+        #[cfg(target_os = "linux")]
+        TIMEZONE_TRIGGER_NAME => Ok(Box::new(TimezoneTrigger::from_config(config)?)),
+        #[cfg(target_os = "linux")]
+        USERS_TRIGGER_NAME => Ok(Box::new(UsersTrigger::from_config(config)?)),
         WIFI_TRIGGER_NAME => Ok(Box::new(WifiTrigger::from_config(config)?)),
+        WIFI_SCAN_TRIGGER_NAME => Ok(Box::new(WifiScanTrigger::from_config(config)?)),
+        #[cfg(unix)]
+        WINDOW_TRIGGER_NAME => Ok(Box::new(WindowTrigger::from_config(config)?)),
 
         _ => Err(io::Error::new(
             io::ErrorKind::InvalidData,
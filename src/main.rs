@@ -1,39 +1,74 @@
 #![feature(conservative_impl_trait)]
 
+#[cfg(unix)] extern crate byteorder;
+extern crate chrono;
 #[macro_use] extern crate clap;
 #[macro_use] extern crate futures;
 extern crate futures_stream_select_all;
+extern crate humantime;
+extern crate libc;
+#[cfg(unix)] extern crate mio;
+extern crate regex;
 extern crate serde;
 #[macro_use] extern crate serde_derive;
+#[cfg(unix)] extern crate serde_json;
 extern crate serde_yaml;
 extern crate tokio_core;
+extern crate tokio_io;
+extern crate tokio_process;
+#[cfg(unix)] extern crate tokio_signal;
+#[cfg(unix)] extern crate tokio_uds;
 
 mod actions;
+mod bus;
 mod context;
+#[cfg(unix)]
+mod dbus;
 mod driver;
 mod multi;
+mod paths;
+#[cfg(unix)]
+mod shells;
 mod triggers;
 
 use std::env;
 use std::fs;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::process;
 
 use clap::{Arg, AppSettings};
+#[cfg(unix)]
+use clap::{ArgMatches, SubCommand};
+use futures::Future;
 use futures::future::Executor;
 use tokio_core::reactor::Core;
 
 use context::Config;
-use driver::drive;
+use driver::{drive, Sources};
+#[cfg(unix)]
+use triggers::cwd::TRIGGER_NAME as CWD_TRIGGER_NAME;
 
 const CONFIG_FILE_PARAM: &'static str = "CONFIG_FILE";
 const NO_DAEMON_PARAM: &'static str = "NO_DAEMON";
 const PID_FILE_PARAM: &'static str = "PID_FILE";
 
+#[cfg(unix)]
+const REPORT_CWD_CMD: &'static str = "report-cwd";
+#[cfg(unix)]
+const DIR_PARAM: &'static str = "DIR";
+#[cfg(unix)]
+const SHELL_PID_PARAM: &'static str = "SHELL_PID";
+
 fn main() {
     let default_cfg_file = "~/.config/runtext.yml".to_owned();
     let pid_path = env::temp_dir().join("runtext.pid");
     let default_pid_file = pid_path.to_string_lossy().to_owned();
 
-    let matches = app_from_crate!()
+    let app = app_from_crate!()
         .setting(AppSettings::DisableHelpSubcommand)
         .setting(AppSettings::GlobalVersion)
         .arg(
@@ -61,8 +96,33 @@ fn main() {
                 .default_value(&default_pid_file)
                 .takes_value(true)
                 .global(true)
-        )
-        .get_matches();
+        );
+
+    #[cfg(unix)]
+    let app = app.subcommand(
+        SubCommand::with_name(REPORT_CWD_CMD)
+            .about("Reports a shell's working directory to the daemon, for use in shell hooks.")
+            .arg(
+                Arg::with_name(DIR_PARAM)
+                    .help("The working directory. Defaults to the current directory.")
+                    .long("dir")
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::with_name(SHELL_PID_PARAM)
+                    .help("The PID of the shell. Defaults to the parent process.")
+                    .long("shell-pid")
+                    .takes_value(true)
+            )
+    );
+
+    let matches = app.get_matches();
+
+    #[cfg(unix)]
+    if let Some(matches) = matches.subcommand_matches(REPORT_CWD_CMD) {
+        report_cwd(matches);
+        return;
+    }
 
     let cfg = {
         let path = matches.value_of(CONFIG_FILE_PARAM).unwrap();
@@ -86,8 +146,18 @@ fn start(config: Config) {
     let mut core = Core::new().unwrap();
 
     let handle = core.handle();
+    let sources = Sources::new();
+
+    // Only listen for shells if anyone is interested in them.
+    #[cfg(unix)]
+    if config.iter().any(|ctx| ctx.triggers.contains_key(CWD_TRIGGER_NAME)) {
+        let server = shells::serve(sources.shells.clone(), &shells::socket_path(), &handle)
+            .expect("Failed to listen for working directory reports.");
+        handle.spawn(server.map_err(|err| eprintln!("Stopped receiving working directory reports: {}.", err)));
+    }
+
     let drivers = config.into_iter()
-        .map(|ctx| drive(ctx, handle.clone()))
+        .map(|ctx| drive(ctx, handle.clone(), sources.clone()))
         .map(|d| d.unwrap());
 
     for driver in drivers {
@@ -96,3 +166,25 @@ fn start(config: Config) {
 
     core.run(futures::empty::<(), ()>()).unwrap();
 }
+
+#[cfg(unix)]
+fn report_cwd(matches: &ArgMatches) {
+    let dir = match matches.value_of(DIR_PARAM) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::current_dir().expect("Could not determine the current directory."),
+    };
+    let pid = match matches.value_of(SHELL_PID_PARAM) {
+        Some(pid) => pid.parse().expect("Invalid shell PID."),
+        None => unsafe { libc::getppid() as u32 },
+    };
+
+    match shells::send_report(&shells::socket_path(), pid, &dir) {
+        Ok(()) => {},
+        // The daemon isn't running, which is fine, the hook runs all the time.
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound || e.kind() == io::ErrorKind::ConnectionRefused => {},
+        Err(e) => {
+            eprintln!("Failed to report the working directory: {}.", e);
+            process::exit(1);
+        },
+    }
+}
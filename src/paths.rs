@@ -0,0 +1,35 @@
+//! Helpers for working with user supplied paths.
+
+use std::env;
+use std::path::PathBuf;
+
+/// Expands a leading `~` in the given path to the user's home directory.
+///
+/// Paths not starting with `~` and paths of other users (`~bob/...`) are
+/// returned unchanged.
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = env::var_os("HOME");
+
+    match home {
+        Some(ref home) if path == "~" => PathBuf::from(home),
+        Some(ref home) if path.starts_with("~/") => PathBuf::from(home).join(&path[2..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn expands_home() {
+        let home = PathBuf::from(env::var_os("HOME").unwrap());
+
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~/cal.ics"), home.join("cal.ics"));
+        assert_eq!(expand_home("/tmp/~/cal.ics"), Path::new("/tmp/~/cal.ics"));
+        assert_eq!(expand_home("~bob/cal.ics"), Path::new("~bob/cal.ics"));
+    }
+}
@@ -0,0 +1,188 @@
+//! The working directories of the user's shells, as reported by the
+//! `runtext report-cwd` shell hook over a Unix socket.
+//!
+//! The hook sends a single line `<shell pid> <working directory>` per
+//! connection.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::os::unix::net::{UnixListener as StdUnixListener, UnixStream as StdUnixStream};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use futures::prelude::*;
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use libc;
+use tokio_core::reactor::Handle;
+use tokio_io::io::lines;
+use tokio_uds::UnixListener;
+
+/// A shared registry of the working directories of running shells.
+///
+/// The registry is cheap to clone, all clones refer to the same state.
+#[derive(Clone, Debug, Default)]
+pub struct Shells(Rc<RefCell<Inner>>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cwds: HashMap<u32, PathBuf>,
+    subscribers: Vec<UnboundedSender<()>>,
+}
+
+impl Shells {
+    pub fn new() -> Self {
+        Shells::default()
+    }
+
+    /// Records the working directory of the shell with the given PID.
+    pub fn report(&self, pid: u32, cwd: PathBuf) {
+        let changed = self.0.borrow_mut().cwds.insert(pid, cwd.clone()) != Some(cwd);
+        if changed {
+            self.notify();
+        }
+    }
+
+    /// Forgets about shells that have exited.
+    pub fn prune(&self) {
+        let changed = {
+            let mut inner = self.0.borrow_mut();
+            let count = inner.cwds.len();
+            inner.cwds.retain(|&pid, _| is_running(pid));
+            inner.cwds.len() != count
+        };
+
+        if changed {
+            self.notify();
+        }
+    }
+
+    /// Gets the working directories of all known shells.
+    pub fn cwds(&self) -> Vec<PathBuf> {
+        self.0.borrow().cwds.values().cloned().collect()
+    }
+
+    /// Subscribes to changes of the working directories.
+    pub fn subscribe(&self) -> UnboundedReceiver<()> {
+        let (tx, rx) = mpsc::unbounded();
+        self.0.borrow_mut().subscribers.push(tx);
+
+        rx
+    }
+
+    fn notify(&self) {
+        self.0.borrow_mut().subscribers.retain(|tx| tx.unbounded_send(()).is_ok());
+    }
+}
+
+/// Gets the path of the socket the daemon receives reports on.
+pub fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("runtext.sock"),
+        None => env::temp_dir().join(format!("runtext-{}.sock", unsafe { libc::getuid() })),
+    }
+}
+
+/// Listens for reports on the socket at the given path and records
+/// them in the registry.
+pub fn serve(shells: Shells, path: &Path, handle: &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> {
+    // Remove the socket of a previous, crashed instance.
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() != io::ErrorKind::NotFound => return Err(io::Error::new(e.kind(), e.to_string())),
+        _ => {},
+    }
+
+    let listener = StdUnixListener::bind(path)?;
+    let listener = UnixListener::from_std(listener, handle.new_tokio_handle())?;
+
+    let h = handle.clone();
+    let server = listener.incoming().for_each(move |conn| {
+        let shells = shells.clone();
+        let reports = lines(BufReader::new(conn))
+            .for_each(move |line| {
+                match parse_report(&line) {
+                    Some((pid, cwd)) => shells.report(pid, cwd),
+                    None => eprintln!("Ignoring invalid working directory report '{}'.", line),
+                }
+
+                Ok(())
+            })
+            .map_err(|err| eprintln!("Failed to read working directory report: {}.", err));
+
+        h.spawn(reports);
+        Ok(())
+    });
+
+    Ok(Box::new(server))
+}
+
+/// Sends the working directory of a shell to the daemon.
+pub fn send_report(path: &Path, pid: u32, cwd: &Path) -> io::Result<()> {
+    let mut conn = StdUnixStream::connect(path)?;
+    writeln!(conn, "{} {}", pid, cwd.display())
+}
+
+fn parse_report(line: &str) -> Option<(u32, PathBuf)> {
+    let mut parts = line.splitn(2, ' ');
+    let pid = parts.next()?.parse().ok()?;
+    let cwd = parts.next().filter(|cwd| cwd.starts_with('/'))?;
+
+    Some((pid, PathBuf::from(cwd)))
+}
+
+fn is_running(pid: u32) -> bool {
+    unsafe {
+        libc::kill(pid as libc::pid_t, 0) == 0 ||
+            io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn parse_reports() {
+        assert_eq!(parse_report("42 /home/alice/src/my project"), Some((42, PathBuf::from("/home/alice/src/my project"))));
+        assert_eq!(parse_report("42 relative"), None);
+        assert_eq!(parse_report("/home/alice"), None);
+    }
+
+    #[test]
+    fn prunes_exited_shells() {
+        let shells = Shells::new();
+        let rx = shells.subscribe();
+
+        shells.report(process::id(), PathBuf::from("/tmp"));
+        shells.report(process::id(), PathBuf::from("/tmp"));
+        shells.report(u32::max_value() / 2, PathBuf::from("/home"));
+        shells.prune();
+        assert_eq!(shells.cwds(), vec![PathBuf::from("/tmp")]);
+
+        drop(shells);
+        assert_eq!(rx.collect().wait().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn receives_reports() {
+        let path = env::temp_dir().join(format!("runtext-shells-{}.sock", process::id()));
+        let shells = Shells::new();
+
+        let mut core = Core::new().unwrap();
+        let server = serve(shells.clone(), &path, &core.handle()).unwrap();
+        core.handle().spawn(server.map_err(|_| ()));
+
+        send_report(&path, 42, Path::new("/srv/project")).unwrap();
+        let (_, rx) = core.run(shells.subscribe().into_future()).map_err(|_| ()).unwrap();
+        drop(rx);
+
+        assert_eq!(shells.cwds(), vec![PathBuf::from("/srv/project")]);
+        fs::remove_file(path).unwrap();
+    }
+}
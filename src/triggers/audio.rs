@@ -0,0 +1,222 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use futures::future;
+use futures::prelude::*;
+use futures::stream;
+use regex::Regex;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+use tokio_process::CommandExt;
+
+use triggers::{changes, Activity, Trigger};
+use triggers::lines::ChildLines;
+
+pub const TRIGGER_NAME: &'static str = "audio_device";
+
+/// An evidence source that is active while a PulseAudio (or PipeWire)
+/// sink is present, e.g. while a headset is plugged in, or while it is
+/// the default sink.
+///
+/// Changes are picked up by subscribing to the sound server's events
+/// via `pactl subscribe`.
+#[derive(Clone, Debug)]
+pub struct AudioTrigger {
+    sink: Regex,
+    default: bool,
+    pactl: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct AudioConfig {
+    sink: String,
+    #[serde(default)]
+    default: bool,
+    pactl: Option<String>,
+}
+
+impl AudioTrigger {
+    /// Creates a new `AudioTrigger` for the sinks whose names match
+    /// the given pattern.
+    ///
+    /// If `default` is set, the sink additionally has to be the default.
+    pub fn new(sink: &str, default: bool) -> io::Result<Self> {
+        let sink = Regex::new(sink)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid sink pattern: {}", e)))?;
+
+        Ok(AudioTrigger {
+            sink,
+            default,
+            pactl: PathBuf::from("pactl"),
+        })
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Value::String(ref sink) = *cfg {
+            return Self::new(sink, false);
+        }
+
+        let cfg: AudioConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid audio_device configuration: {}", e)))?;
+
+        let mut trigger = Self::new(&cfg.sink, cfg.default)?;
+        if let Some(pactl) = cfg.pactl {
+            trigger.pactl = PathBuf::from(pactl);
+        }
+
+        Ok(trigger)
+    }
+
+    /// Checks whether a matching sink is present or the default.
+    fn is_active(&self, handle: &Handle) -> Box<Future<Item = bool, Error = io::Error>> {
+        let pattern = self.sink.clone();
+
+        if self.default {
+            Box::new(self.pactl(&["info"], handle).map(move |info| {
+                let default_sink = info.lines()
+                    .filter_map(|line| {
+                        let line = line.trim();
+                        if line.starts_with("Default Sink: ") {
+                            Some(line["Default Sink: ".len()..].trim())
+                        } else {
+                            None
+                        }
+                    })
+                    .next();
+
+                default_sink.map_or(false, |sink| pattern.is_match(sink))
+            }))
+        } else {
+            // Each line looks like `52	alsa_output.usb-headset.analog-stereo	PipeWire	s16le 2ch 48000Hz	SUSPENDED`.
+            Box::new(self.pactl(&["list", "short", "sinks"], handle).map(move |sinks| {
+                sinks.lines()
+                    .filter_map(|line| line.split('\t').nth(1))
+                    .any(|sink| pattern.is_match(sink))
+            }))
+        }
+    }
+
+    fn pactl(&self, args: &[&str], handle: &Handle) -> Box<Future<Item = String, Error = io::Error>> {
+        let output = Command::new(&self.pactl)
+            .args(args)
+            .env("LC_ALL", "C")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output_async_with_handle(handle.new_tokio_handle())
+            .and_then(|output| {
+                if !output.status.success() {
+                    return Err(io::Error::new(io::ErrorKind::Other, "pactl failed to query the sound server."));
+                }
+
+                Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            });
+
+        Box::new(output)
+    }
+}
+
+impl Trigger for AudioTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let mut cmd = Command::new(&self.pactl);
+        cmd.arg("subscribe").env("LC_ALL", "C");
+
+        let events = match ChildLines::spawn(&mut cmd, &handle) {
+            Ok(events) => events,
+            Err(err) => return Box::new(future::err(err).into_stream()),
+        };
+
+        // Sinks being added or removed are reported as sink events, changes
+        // of the default sink as server events.
+        let relevant = events.filter(|line| line.contains(" on sink ") || line.contains(" on server"));
+        let trigger = self.clone();
+        let stream = stream::once(Ok(String::new()))
+            .chain(relevant)
+            .and_then(move |_| trigger.is_active(&handle));
+
+        Box::new(changes(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    /// Creates a fake `pactl` whose sinks are read from a file next to it,
+    /// and which reports a sink event once that file changes.
+    fn fake_pactl(dir: &PathBuf) -> PathBuf {
+        let pactl = dir.join("pactl");
+        let mut script = File::create(&pactl).unwrap();
+        write!(script, "#!/bin/sh
+DIR='{}'
+case \"$1\" in
+    info) echo \"Default Sink: $(head -n1 \"$DIR/sinks\" | cut -f2)\" ;;
+    list) cat \"$DIR/sinks\" ;;
+    subscribe)
+        while [ ! -e \"$DIR/plugged\" ]; do sleep 0.05; done
+        printf '52\\theadset\\n' >> \"$DIR/sinks\"
+        echo \"Event 'new' on sink #52\"
+        exec sleep 10 ;;
+esac
+", dir.display()).unwrap();
+        drop(script);
+        fs::set_permissions(&pactl, fs::Permissions::from_mode(0o755)).unwrap();
+
+        pactl
+    }
+
+    #[test]
+    fn load_cfg() {
+        let trigger = AudioTrigger::from_config(&Value::String("^bluez_output\\.".to_owned())).unwrap();
+        assert!(!trigger.default);
+
+        let cfg = serde_yaml::from_str("{ sink: headset, default: true, pactl: /opt/bin/pactl }").unwrap();
+        let trigger = AudioTrigger::from_config(&cfg).unwrap();
+        assert!(trigger.default);
+        assert_eq!(trigger.pactl, PathBuf::from("/opt/bin/pactl"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        AudioTrigger::from_config(&Value::String("(headset".to_owned())).unwrap();
+    }
+
+    #[test]
+    fn follows_sinks() {
+        let dir = env::temp_dir().join(format!("runtext-audio-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("sinks")).unwrap()
+            .write_all(b"1\tspeakers\tPipeWire\n").unwrap();
+
+        let mut trigger = AudioTrigger::new("^headset$", false).unwrap();
+        trigger.pactl = fake_pactl(&dir);
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let mut default_trigger = trigger.clone();
+        default_trigger.default = true;
+        assert!(!core.run(default_trigger.is_active(&handle)).unwrap());
+        default_trigger.sink = Regex::new("speakers").unwrap();
+        assert!(core.run(default_trigger.is_active(&handle)).unwrap());
+
+        let stream = trigger.listen(core.handle());
+        File::create(dir.join("plugged")).unwrap();
+
+        let (activity, _) = core.run(stream.into_future())
+            .map_err(|(err, _)| err)
+            .unwrap();
+        assert_eq!(activity, Some(Activity::Active));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,746 @@
+//! A minimal parser for iCalendar (RFC 5545) files.
+//!
+//! Only what's necessary to find out when events take place is supported.
+//! Times with a `TZID` are interpreted in the local time zone of the system.
+
+use std::collections::HashMap;
+use std::io;
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+
+/// The maximum amount of recurrence periods looked at when expanding
+/// a recurring event, guards against rules that never match.
+const MAX_PERIODS: i64 = 100000;
+
+/// An event read from an iCalendar file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub uid: Option<String>,
+    pub summary: String,
+    pub categories: Vec<String>,
+    pub start: Time,
+    pub duration: Duration,
+    pub rule: Option<Rule>,
+    pub exdates: Vec<Time>,
+    pub recurrence_id: Option<Time>,
+    pub cancelled: bool,
+}
+
+/// A point in time as found in iCalendar files.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Time {
+    pub date_time: NaiveDateTime,
+
+    /// Whether the time is given in UTC or in local time.
+    pub is_utc: bool,
+
+    /// Whether only the date is given.
+    pub is_date: bool,
+}
+
+/// A recurrence rule (`RRULE`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<Time>,
+
+    /// Weekdays with an optional ordinal, e.g. `-1FR` for the last friday.
+    pub by_day: Vec<(i32, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A content line of an iCalendar file.
+#[derive(Debug)]
+struct Property {
+    name: String,
+    params: HashMap<String, String>,
+    value: String,
+}
+
+/// Parses the events out of the given iCalendar file content.
+///
+/// Recurrence overrides (events with a `RECURRENCE-ID`) are excluded from
+/// the recurring event they belong to. Events that can't be read, e.g.
+/// because of an unsupported recurrence rule, are skipped.
+pub fn parse(content: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut current: Option<Vec<Property>> = None;
+
+    for line in unfold(content) {
+        let prop = match parse_property(&line) {
+            Some(prop) => prop,
+            None => continue,
+        };
+
+        match (prop.name.as_str(), prop.value.to_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => current = Some(Vec::new()),
+            ("END", "VEVENT") => if let Some(props) = current.take() {
+                match Event::from_properties(props) {
+                    Ok(event) => events.push(event),
+                    Err(err) => eprintln!("Skipping calendar event: {}", err),
+                }
+            },
+            _ => if let Some(ref mut props) = current {
+                props.push(prop);
+            },
+        }
+    }
+
+    let overridden = events.iter()
+        .filter_map(|ev| match (&ev.uid, ev.recurrence_id) {
+            (&Some(ref uid), Some(id)) => Some((uid.clone(), id)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for ev in events.iter_mut().filter(|ev| ev.rule.is_some()) {
+        let uid = ev.uid.clone();
+        ev.exdates.extend(overridden.iter()
+            .filter(|&&(ref id, _)| Some(id) == uid.as_ref())
+            .map(|&(_, time)| time));
+    }
+
+    events.into_iter().filter(|ev| !ev.cancelled).collect()
+}
+
+impl Event {
+    fn from_properties(props: Vec<Property>) -> io::Result<Self> {
+        let mut uid = None;
+        let mut summary = String::new();
+        let mut categories = Vec::new();
+        let mut start = None;
+        let mut end = None;
+        let mut duration = None;
+        let mut rule = None;
+        let mut exdates = Vec::new();
+        let mut recurrence_id = None;
+        let mut cancelled = false;
+
+        for prop in props {
+            match prop.name.as_str() {
+                "UID" => uid = Some(prop.value),
+                "SUMMARY" => summary = unescape(&prop.value),
+                "CATEGORIES" => categories.extend(split_list(&prop.value).iter().map(|c| unescape(c))),
+                "DTSTART" => start = Some(Time::from_property(&prop, &prop.value)?),
+                "DTEND" => end = Some(Time::from_property(&prop, &prop.value)?),
+                "DURATION" => duration = Some(parse_duration(&prop.value)?),
+                "RRULE" => rule = Some(prop.value.parse()?),
+                "EXDATE" => for value in prop.value.split(',') {
+                    exdates.push(Time::from_property(&prop, value)?);
+                },
+                "RECURRENCE-ID" => recurrence_id = Some(Time::from_property(&prop, &prop.value)?),
+                "STATUS" => cancelled = prop.value.eq_ignore_ascii_case("CANCELLED"),
+                _ => {},
+            }
+        }
+
+        let start: Time = start.ok_or(invalid("Event is missing DTSTART."))?;
+        let duration = match (end, duration) {
+            (_, Some(duration)) => duration,
+            (Some(end), None) if end.is_utc == start.is_utc => end.date_time.signed_duration_since(start.date_time),
+            (Some(end), None) => Duration::seconds(end.timestamp() - start.timestamp()),
+            (None, None) if start.is_date => Duration::days(1),
+            (None, None) => Duration::zero(),
+        };
+
+        Ok(Event {
+            uid,
+            summary,
+            categories,
+            start,
+            duration,
+            rule,
+            exdates,
+            recurrence_id,
+            cancelled,
+        })
+    }
+
+    /// Computes the time spans the event takes place in, as far as they
+    /// overlap the span from `from` to `to`.
+    ///
+    /// All points in time are given as seconds since the unix epoch.
+    pub fn occurrences(&self, from: i64, to: i64) -> Vec<(i64, i64)> {
+        let starts = match self.rule {
+            Some(ref rule) => rule.expand(self.start, from - self.duration.num_seconds(), to),
+            None => vec![self.start.date_time],
+        };
+
+        starts.into_iter()
+            .filter(|&start| !self.exdates.iter().any(|ex| ex.matches(start)))
+            .map(|start| {
+                let end = start + self.duration;
+                (self.start.with(start).timestamp(), self.start.with(end).timestamp())
+            })
+            .filter(|&(start, end)| start < to && end > from)
+            .collect()
+    }
+}
+
+impl Time {
+    fn from_property(prop: &Property, value: &str) -> io::Result<Self> {
+        let is_date = prop.params.get("VALUE").map_or(value.len() == 8, |v| v == "DATE");
+        Self::parse(value, is_date)
+    }
+
+    fn parse(value: &str, is_date: bool) -> io::Result<Self> {
+        let value = value.trim();
+        let error = || invalid(format!("Invalid date or time '{}'.", value));
+
+        if is_date {
+            let date = NaiveDate::parse_from_str(value, "%Y%m%d").map_err(|_| error())?;
+
+            Ok(Time { date_time: date.and_hms_opt(0, 0, 0).unwrap(), is_utc: false, is_date })
+        } else {
+            let is_utc = value.ends_with('Z');
+            let date_time = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+                .map_err(|_| error())?;
+
+            Ok(Time { date_time, is_utc, is_date })
+        }
+    }
+
+    /// Creates a new time of the same kind at the given date and time.
+    fn with(&self, date_time: NaiveDateTime) -> Self {
+        Time { date_time, ..*self }
+    }
+
+    /// Checks whether the given start of an occurrence is denoted by this time.
+    fn matches(&self, start: NaiveDateTime) -> bool {
+        if self.is_date {
+            self.date_time.date() == start.date()
+        } else {
+            self.date_time == start
+        }
+    }
+
+    /// Converts the time to seconds since the unix epoch.
+    pub fn timestamp(&self) -> i64 {
+        if self.is_utc {
+            return Utc.from_utc_datetime(&self.date_time).timestamp();
+        }
+
+        // Local times skipped by a DST transition are moved past the gap.
+        Local.from_local_datetime(&self.date_time).earliest()
+            .or_else(|| Local.from_local_datetime(&(self.date_time + Duration::hours(1))).earliest())
+            .map(|t| t.timestamp())
+            .unwrap_or_else(|| Utc.from_utc_datetime(&self.date_time).timestamp())
+    }
+}
+
+impl Rule {
+    /// Expands the rule into the starts of the occurrences it denotes,
+    /// beginning with the event's start and ending past `to`.
+    ///
+    /// Occurrences ending before `from` may be left out.
+    fn expand(&self, first: Time, from: i64, to: i64) -> Vec<NaiveDateTime> {
+        let start = first.date_time;
+
+        // Counted rules have to be expanded from the beginning.
+        let first_period = match self.count {
+            Some(_) => 0,
+            None => (self.periods_between(start.date(), from) - 1).max(0),
+        };
+        let until = self.until.map(|t| t.timestamp());
+
+        let mut starts = Vec::new();
+        let mut seen = 0;
+        for period in first_period..first_period + MAX_PERIODS {
+            let (period_start, dates) = self.dates_in_period(start.date(), period);
+            if Utc.from_utc_datetime(&period_start.and_hms_opt(0, 0, 0).unwrap()).timestamp() > to + 2 * DAY {
+                break;
+            }
+
+            for date in dates {
+                let occurrence = date.and_time(start.time());
+                if occurrence < start {
+                    continue;
+                }
+
+                if until.map_or(false, |until| first.with(occurrence).timestamp() > until) {
+                    return starts;
+                }
+                seen += 1;
+                if self.count.map_or(false, |count| seen > count) {
+                    return starts;
+                }
+
+                starts.push(occurrence);
+            }
+        }
+
+        starts
+    }
+
+    /// Estimates how many recurrence periods lie between `start` and `ts`.
+    fn periods_between(&self, start: NaiveDate, ts: i64) -> i64 {
+        let date = Utc.timestamp_opt(ts, 0).single()
+            .map(|t| t.naive_utc().date())
+            .unwrap_or(start);
+        let interval = self.interval as i64;
+
+        match self.frequency {
+            Frequency::Daily => date.signed_duration_since(start).num_days() / interval,
+            Frequency::Weekly => date.signed_duration_since(start).num_weeks() / interval,
+            Frequency::Monthly => (months(date) - months(start)) / interval,
+            Frequency::Yearly => (date.year() - start.year()) as i64 / interval,
+        }
+    }
+
+    /// Computes the first day of the given recurrence period and the dates
+    /// in it the rule matches.
+    fn dates_in_period(&self, start: NaiveDate, period: i64) -> (NaiveDate, Vec<NaiveDate>) {
+        let offset = period * self.interval as i64;
+
+        let (period_start, mut dates) = match self.frequency {
+            Frequency::Daily => {
+                let date = start + Duration::days(offset);
+                let matches = self.by_month_day.is_empty() ||
+                    self.by_month_day.iter().any(|&d| month_day(date.year(), date.month(), d) == Some(date));
+                let matches = matches && (self.by_day.is_empty() ||
+                    self.by_day.iter().any(|&(_, wd)| wd == date.weekday()));
+
+                (date, if matches { vec![date] } else { vec![] })
+            },
+            Frequency::Weekly => {
+                let week_start = start - Duration::days(start.weekday().num_days_from_monday() as i64) +
+                    Duration::weeks(offset);
+                let weekdays = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|&(_, wd)| wd).collect()
+                };
+
+                let dates = weekdays.into_iter()
+                    .map(|wd| week_start + Duration::days(wd.num_days_from_monday() as i64))
+                    .collect();
+                (week_start, dates)
+            },
+            Frequency::Monthly => {
+                let month = months(start) + offset;
+                let (year, month) = ((month / 12) as i32, (month % 12) as u32 + 1);
+
+                (first_of_month(year, month), self.dates_in_month(year, month, start.day()))
+            },
+            Frequency::Yearly => {
+                let year = start.year() + offset as i32;
+                let months = if self.by_month.is_empty() { vec![start.month()] } else { self.by_month.clone() };
+
+                let dates = months.into_iter()
+                    .flat_map(|month| self.dates_in_month(year, month, start.day()))
+                    .collect();
+                (first_of_month(year, 1), dates)
+            },
+        };
+
+        if !self.by_month.is_empty() {
+            dates.retain(|date| self.by_month.contains(&date.month()));
+        }
+        dates.sort();
+        dates.dedup();
+
+        (period_start, dates)
+    }
+
+    /// Computes the dates in the given month the rule matches, using the day
+    /// of the event's start if the rule doesn't say otherwise.
+    fn dates_in_month(&self, year: i32, month: u32, default_day: u32) -> Vec<NaiveDate> {
+        if !self.by_month_day.is_empty() {
+            return self.by_month_day.iter()
+                .filter_map(|&day| month_day(year, month, day))
+                .collect();
+        }
+        if self.by_day.is_empty() {
+            return NaiveDate::from_ymd_opt(year, month, default_day).into_iter().collect();
+        }
+
+        let days_in_month = (1..32)
+            .filter_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+            .collect::<Vec<_>>();
+        self.by_day.iter()
+            .flat_map(|&(ordinal, weekday)| {
+                let matching = days_in_month.iter()
+                    .cloned()
+                    .filter(|date| date.weekday() == weekday)
+                    .collect::<Vec<_>>();
+
+                match ordinal {
+                    0 => matching,
+                    n if n > 0 => matching.get(n as usize - 1).cloned().into_iter().collect(),
+                    n => matching.len().checked_sub((-n) as usize)
+                        .and_then(|idx| matching.get(idx).cloned())
+                        .into_iter()
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl ::std::str::FromStr for Rule {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut frequency = None;
+        let mut rule = Rule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+        };
+        let error = || invalid(format!("Invalid or unsupported recurrence rule '{}'.", s));
+
+        for part in s.split(';') {
+            let mut kv = part.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim().to_uppercase();
+            let value = kv.next().unwrap_or("").trim();
+
+            match key.as_str() {
+                "FREQ" => frequency = Some(match value.to_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return Err(error()),
+                }),
+                "INTERVAL" => rule.interval = value.parse().ok().filter(|&i| i > 0).ok_or_else(error)?,
+                "COUNT" => rule.count = Some(value.parse().map_err(|_| error())?),
+                "UNTIL" => rule.until = Some(Time::parse(value, value.len() == 8)?),
+                "BYDAY" => for day in value.split(',') {
+                    rule.by_day.push(parse_weekday(day).ok_or_else(error)?);
+                },
+                "BYMONTHDAY" => for day in value.split(',') {
+                    rule.by_month_day.push(day.parse().map_err(|_| error())?);
+                },
+                "BYMONTH" => for month in value.split(',') {
+                    rule.by_month.push(month.parse().map_err(|_| error())?);
+                },
+                "WKST" | "" => {},
+                _ => return Err(error()),
+            }
+        }
+
+        rule.frequency = frequency.ok_or_else(error)?;
+        Ok(rule)
+    }
+}
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Joins lines continued by leading whitespace.
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim_end_matches('\r');
+
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        lines.push(line.to_owned());
+    }
+
+    lines
+}
+
+/// Parses a content line like `DTSTART;TZID=Europe/Berlin:20180101T100000`.
+fn parse_property(line: &str) -> Option<Property> {
+    let mut in_quotes = false;
+    let colon = line.char_indices()
+        .find(|&(_, c)| {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
+            c == ':' && !in_quotes
+        })
+        .map(|(idx, _)| idx)?;
+
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_uppercase();
+    let params = parts
+        .filter_map(|param| {
+            let mut kv = param.splitn(2, '=');
+            let key = kv.next()?.trim().to_uppercase();
+            let value = kv.next()?.trim().trim_matches('"').to_owned();
+
+            Some((key, value))
+        })
+        .collect();
+
+    Some(Property { name, params, value: value.to_owned() })
+}
+
+/// Parses a duration like `PT1H30M` or `-P1D`.
+fn parse_duration(value: &str) -> io::Result<Duration> {
+    let error = || invalid(format!("Invalid duration '{}'.", value));
+    let value = value.trim();
+    let (negative, value) = match value.chars().next() {
+        Some('-') => (true, &value[1..]),
+        Some('+') => (false, &value[1..]),
+        _ => (false, value),
+    };
+    if !value.starts_with('P') {
+        return Err(error());
+    }
+
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in value[1..].chars() {
+        let unit = match c {
+            c if c.is_digit(10) => {
+                number.push(c);
+                continue;
+            },
+            'T' => continue,
+            'W' => 7 * DAY,
+            'D' => DAY,
+            'H' => 60 * 60,
+            'M' => 60,
+            'S' => 1,
+            _ => return Err(error()),
+        };
+
+        seconds += number.parse::<i64>().map_err(|_| error())? * unit;
+        number.clear();
+    }
+
+    Ok(Duration::seconds(if negative { -seconds } else { seconds }))
+}
+
+/// Parses a weekday with an optional ordinal like `MO`, `2TU` or `-1FR`.
+fn parse_weekday(value: &str) -> Option<(i32, Weekday)> {
+    let value = value.trim();
+    if value.len() < 2 {
+        return None;
+    }
+
+    let (ordinal, day) = value.split_at(value.len() - 2);
+    let ordinal = match ordinal {
+        "" => 0,
+        o => o.trim_start_matches('+').parse().ok()?,
+    };
+    let weekday = match day.to_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+
+    Some((ordinal, weekday))
+}
+
+/// Splits a comma separated list, respecting escaped commas.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut escaped = false;
+
+    for c in value.chars() {
+        match c {
+            ',' if !escaped => items.push(String::new()),
+            _ => items.last_mut().unwrap().push(c),
+        }
+        escaped = c == '\\' && !escaped;
+    }
+
+    items.into_iter()
+        .map(|item| item.trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Removes the escaping from text values.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') | Some('N') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => {},
+        }
+    }
+
+    result
+}
+
+/// Counts the months since year zero.
+fn months(date: NaiveDate) -> i64 {
+    date.year() as i64 * 12 + date.month0() as i64
+}
+
+fn first_of_month(year: i32, month: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, 1).unwrap_or(NaiveDate::from_ymd_opt(year, 1, 1).unwrap())
+}
+
+/// Gets the given day of the month, counting from the end if negative.
+fn month_day(year: i32, month: u32, day: i32) -> Option<NaiveDate> {
+    if day > 0 {
+        return NaiveDate::from_ymd_opt(year, month, day as u32);
+    }
+
+    let last = (28..32).rev()
+        .filter_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+        .next()?;
+    let day = last.day() as i32 + day + 1;
+    if day < 1 {
+        return None;
+    }
+
+    NaiveDate::from_ymd_opt(year, month, day as u32)
+}
+
+fn invalid<E: Into<String>>(msg: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &'static str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:standup\r
+SUMMARY:Daily stand-up\\, team A\r
+CATEGORIES:Meeting,Work\r
+DTSTART:20180102T090000Z\r
+DURATION:PT15M\r
+RRULE:FREQ=WEEKLY;BYDAY=TU,TH;COUNT=4\r
+EXDATE:20180104T090000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+RECURRENCE-ID:20180109T090000Z\r
+SUMMARY:Daily stand-up\\, team A (moved)\r
+DTSTART:20180109T100000Z\r
+DTEND:20180109T101500Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:retro\r
+SUMMARY:Retrospective\r
+DTSTART:20180105T140000Z\r
+DTEND:20180105T150000Z\r
+STATUS:CANCELLED\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:christmas\r
+SUMMARY:Christmas\r
+CATEGORIES:Holiday\r
+DTSTART;VALUE=DATE:20171225\r
+RRULE:FREQ=YEARLY\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:thanksgiving\r
+SUMMARY:Thanks\r
+ giving\r
+DTSTART;VALUE=DATE:20171123\r
+RRULE:FREQ=YEARLY;BYMONTH=11;BYDAY=4TH\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    /// 2018-01-01 00:00 UTC.
+    const JAN_2018: i64 = 1514764800;
+
+    fn ts(s: &str) -> i64 {
+        Time::parse(s, false).unwrap().timestamp()
+    }
+
+    #[test]
+    fn parse_events() {
+        let events = parse(CALENDAR);
+
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0].summary, "Daily stand-up, team A");
+        assert_eq!(events[0].categories, vec!["Meeting", "Work"]);
+        assert_eq!(events[0].duration, Duration::minutes(15));
+        assert_eq!(events[1].duration, Duration::minutes(15));
+        assert_eq!(events[2].duration, Duration::days(1));
+        assert_eq!(events[3].summary, "Thanksgiving");
+    }
+
+    #[test]
+    fn expand_weekly() {
+        let events = parse(CALENDAR);
+
+        let mut occurrences = events[0].occurrences(JAN_2018, JAN_2018 + 60 * DAY);
+        occurrences.extend(events[1].occurrences(JAN_2018, JAN_2018 + 60 * DAY));
+        occurrences.sort();
+        assert_eq!(occurrences, vec![
+            (ts("20180102T090000Z"), ts("20180102T091500Z")),
+            (ts("20180109T100000Z"), ts("20180109T101500Z")),
+            (ts("20180111T090000Z"), ts("20180111T091500Z")),
+        ]);
+    }
+
+    #[test]
+    fn expand_yearly() {
+        let events = parse(CALENDAR);
+        let from = JAN_2018 + 300 * DAY;
+
+        let christmas = events[2].occurrences(from, from + 100 * DAY);
+        assert_eq!(christmas.len(), 1);
+        assert_eq!(christmas[0].0, Time::parse("20181225", true).unwrap().timestamp());
+
+        let thanksgiving = events[3].occurrences(from, from + 100 * DAY);
+        assert_eq!(thanksgiving.len(), 1);
+        assert_eq!(thanksgiving[0].0, Time::parse("20181122", true).unwrap().timestamp());
+    }
+
+    #[test]
+    fn expand_monthly_last_friday() {
+        let rule: Rule = "FREQ=MONTHLY;BYDAY=-1FR;UNTIL=20180401T000000Z".parse().unwrap();
+        let start = Time::parse("20180126T170000", false).unwrap();
+
+        let starts = rule.expand(start, JAN_2018, JAN_2018 + 365 * DAY);
+        let days = starts.iter().map(|s| (s.month(), s.day())).collect::<Vec<_>>();
+        assert_eq!(days, vec![(1, 26), (2, 23), (3, 30)]);
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("PT1H30M").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("P1W").unwrap(), Duration::weeks(1));
+        assert_eq!(parse_duration("-P1DT1S").unwrap(), Duration::seconds(-DAY - 1));
+        assert!(parse_duration("1H").is_err());
+    }
+
+    #[test]
+    fn parse_unsupported_rule() {
+        assert!("FREQ=HOURLY".parse::<Rule>().is_err());
+
+        let calendar = CALENDAR.replacen("RRULE:FREQ=YEARLY\r", "RRULE:FREQ=HOURLY\r", 1);
+        let events = parse(&calendar);
+
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(|ev| ev.summary != "Christmas"));
+        assert_eq!(events[2].summary, "Thanksgiving");
+    }
+}
@@ -0,0 +1,199 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use futures::future;
+use futures::prelude::*;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use multi::Multi;
+use paths::expand_home;
+use triggers::{Activity, Trigger};
+use triggers::schedule::{Schedule, ScheduleStream};
+
+use self::ics::Event;
+
+mod ics;
+
+pub const TRIGGER_NAME: &'static str = "calendar";
+
+/// An evidence source that is active during the events of a local
+/// iCalendar (`.ics`) file, like meetings or public holidays.
+///
+/// The file is read again whenever it changes.
+#[derive(Debug)]
+pub struct CalendarTrigger(CalendarSchedule);
+
+/// The schedule of a `CalendarTrigger`.
+#[derive(Clone, Debug)]
+pub struct CalendarSchedule {
+    path: PathBuf,
+    summary: Option<String>,
+    categories: Vec<String>,
+    events: Vec<Event>,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarConfig {
+    file: String,
+    summary: Option<String>,
+    categories: Option<Multi<String>>,
+}
+
+impl CalendarTrigger {
+    /// Creates a new `CalendarTrigger` reading the given file.
+    ///
+    /// Only events whose summary contains `summary` and that have one of
+    /// the given categories are considered, if specified.
+    pub fn new<P: Into<PathBuf>>(path: P, summary: Option<String>, categories: Vec<String>) -> io::Result<Self> {
+        let mut schedule = CalendarSchedule {
+            path: path.into(),
+            summary: summary.map(|s| s.to_lowercase()),
+            categories: categories.into_iter().map(|c| c.to_lowercase()).collect(),
+            events: Vec::new(),
+            modified: None,
+        };
+        schedule.reload()?;
+
+        Ok(CalendarTrigger(schedule))
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Value::String(ref path) = *cfg {
+            return Self::new(expand_home(path), None, Vec::new());
+        }
+
+        let cfg: CalendarConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid calendar configuration: {}", e)))?;
+        let categories = cfg.categories
+            .map(|c| c.into_iter().collect())
+            .unwrap_or_default();
+
+        Self::new(expand_home(&cfg.file), cfg.summary, categories)
+    }
+}
+
+impl Trigger for CalendarTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        match ScheduleStream::new(self.0.clone(), &handle) {
+            Ok(stream) => Box::new(stream),
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+impl CalendarSchedule {
+    /// Reads the calendar file again if it has been modified.
+    fn reload(&mut self) -> io::Result<()> {
+        let modified = fs::metadata(&self.path)?.modified().ok();
+        if modified.is_some() && modified == self.modified {
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(&self.path)?;
+        let events = ics::parse(&content)
+            .into_iter()
+            .filter(|ev| self.is_relevant(ev))
+            .collect();
+
+        self.events = events;
+        self.modified = modified;
+        Ok(())
+    }
+
+    fn is_relevant(&self, event: &Event) -> bool {
+        let summary_matches = self.summary.as_ref()
+            .map_or(true, |s| event.summary.to_lowercase().contains(s.as_str()));
+        let category_matches = self.categories.is_empty() ||
+            event.categories.iter().any(|c| self.categories.contains(&c.to_lowercase()));
+
+        summary_matches && category_matches
+    }
+}
+
+impl Schedule for CalendarSchedule {
+    fn intervals(&mut self, from: i64, to: i64) -> io::Result<Vec<(i64, i64)>> {
+        // Keep going with the events we know of if the file is
+        // temporarily unavailable, e.g. while it's being replaced.
+        if let Err(err) = self.reload() {
+            eprintln!("Could not read calendar '{}': {}.", self.path.display(), err);
+        }
+
+        let intervals = self.events.iter()
+            .flat_map(|ev| ev.occurrences(from, to))
+            .collect();
+        Ok(intervals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+
+    use super::*;
+
+    fn write_calendar(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("runtext-{}-{}.ics", name, ::std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"BEGIN:VCALENDAR
+BEGIN:VEVENT
+SUMMARY:Weekly Meeting
+CATEGORIES:Work
+DTSTART:20180101T100000Z
+DTEND:20180101T110000Z
+RRULE:FREQ=WEEKLY
+END:VEVENT
+BEGIN:VEVENT
+SUMMARY:New Year
+CATEGORIES:Holiday
+DTSTART;VALUE=DATE:20180101
+RRULE:FREQ=YEARLY
+END:VEVENT
+END:VCALENDAR
+").unwrap();
+
+        path
+    }
+
+    #[test]
+    fn filter_events() {
+        let path = write_calendar("filter");
+
+        let all = CalendarTrigger::new(path.clone(), None, vec![]).unwrap();
+        assert_eq!(all.0.events.len(), 2);
+
+        let meetings = CalendarTrigger::new(path.clone(), Some("meeting".to_owned()), vec![]).unwrap();
+        assert_eq!(meetings.0.events.len(), 1);
+        assert_eq!(meetings.0.events[0].summary, "Weekly Meeting");
+
+        let holidays = CalendarTrigger::new(path.clone(), None, vec!["holiday".to_owned()]).unwrap();
+        assert_eq!(holidays.0.events.len(), 1);
+        assert_eq!(holidays.0.events[0].summary, "New Year");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn weekly_intervals() {
+        let path = write_calendar("intervals");
+        let mut trigger = CalendarTrigger::new(path.clone(), Some("Meeting".to_owned()), vec![]).unwrap();
+
+        // Monday, 2018-10-15 00:00 UTC
+        let monday = 1539561600;
+        let intervals = trigger.0.intervals(monday, monday + 24 * 60 * 60).unwrap();
+        assert_eq!(intervals, vec![(monday + 10 * 60 * 60, monday + 11 * 60 * 60)]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn missing_file() {
+        CalendarTrigger::from_config(&Value::String("/nonexisting/calendar.ics".to_owned())).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+use std::io;
+
+use futures::prelude::*;
+use futures_stream_select_all::select_all;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use bus::Bus;
+use multi::Multi;
+use triggers::{changes, Activity, Trigger};
+
+pub const TRIGGER_NAME: &'static str = "context";
+
+/// An evidence source that signals while other contexts are active.
+///
+/// If multiple contexts are given, all of them have to be active.
+#[derive(Debug)]
+pub struct ContextTrigger {
+    bus: Bus,
+    contexts: Vec<String>,
+}
+
+impl ContextTrigger {
+    pub fn new(contexts: Vec<String>, bus: Bus) -> Self {
+        ContextTrigger { bus, contexts }
+    }
+
+    pub fn from_config(cfg: &Value, bus: Bus) -> io::Result<Self> {
+        Ok(Self::new(Self::dependencies(cfg)?, bus))
+    }
+
+    /// Reads the names of the contexts the trigger depends on
+    /// from its configuration.
+    pub fn dependencies(cfg: &Value) -> io::Result<Vec<String>> {
+        let names: Multi<String> = serde_yaml::from_value(cfg.clone())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Unknown configuration format"))?;
+        let names = names.into_iter().collect::<Vec<_>>();
+
+        if names.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing context names."));
+        }
+
+        Ok(names)
+    }
+}
+
+impl Trigger for ContextTrigger {
+    fn listen(&mut self, _: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let subscriptions = self.contexts.iter()
+            .map(|name| {
+                let name = name.clone();
+                self.bus.subscribe(&name).map(move |act| (name.clone(), act))
+            })
+            .collect::<Vec<_>>();
+
+        let dependency_count = self.contexts.iter().collect::<HashSet<_>>().len();
+        let mut active = HashSet::new();
+        let stream = select_all(subscriptions)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Context bus was closed."))
+            .map(move |(name, act)| {
+                match act {
+                    Activity::Active => active.insert(name),
+                    Activity::Inactive => active.remove(&name),
+                };
+
+                active.len() == dependency_count
+            });
+
+        Box::new(changes(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn load_cfg() {
+        let cfg = Value::String("Office".to_owned());
+        assert_eq!(ContextTrigger::dependencies(&cfg).unwrap(), vec!["Office"]);
+
+        let cfg = Value::Sequence(vec![
+            Value::String("Office".to_owned()),
+            Value::String("Docked".to_owned()),
+        ]);
+        assert_eq!(ContextTrigger::dependencies(&cfg).unwrap(), vec!["Office", "Docked"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        ContextTrigger::dependencies(&Value::Sequence(vec![])).unwrap();
+    }
+
+    #[test]
+    fn follows_all_dependencies() {
+        let core = Core::new().unwrap();
+        let bus = Bus::new();
+
+        let mut trigger = ContextTrigger::new(vec!["Office".to_owned(), "Docked".to_owned()], bus.clone());
+        let stream = trigger.listen(core.handle());
+
+        bus.publish("Office", Activity::Active);
+        bus.publish("Docked", Activity::Active);
+        bus.publish("Office", Activity::Inactive);
+        bus.publish("Docked", Activity::Inactive);
+        drop(trigger);
+        drop(bus);
+
+        let received = stream.collect().wait().unwrap();
+        assert_eq!(received, vec![Activity::Active, Activity::Inactive]);
+    }
+}
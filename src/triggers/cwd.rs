@@ -0,0 +1,125 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::prelude::*;
+use futures::stream;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::{Handle, Interval};
+
+use multi::Multi;
+use paths::expand_home;
+use shells::Shells;
+use triggers::{changes, Activity, Trigger};
+
+pub const TRIGGER_NAME: &'static str = "cwd";
+
+/// How often exited shells are looked for, in seconds.
+const PRUNE_INTERVAL: u64 = 5;
+
+/// An evidence source that is active while any of the user's shells is
+/// inside one of the given directory trees, e.g. a project checkout.
+///
+/// Shells report their working directory through a hook calling
+/// `runtext report-cwd`, e.g. in bash:
+///
+/// ```sh
+/// PROMPT_COMMAND="runtext report-cwd; $PROMPT_COMMAND"
+/// ```
+#[derive(Debug)]
+pub struct CwdTrigger {
+    dirs: Vec<PathBuf>,
+    shells: Shells,
+}
+
+impl CwdTrigger {
+    pub fn new(dirs: Vec<PathBuf>, shells: Shells) -> Self {
+        CwdTrigger { dirs, shells }
+    }
+
+    pub fn from_config(cfg: &Value, shells: Shells) -> io::Result<Self> {
+        let dirs: Multi<String> = serde_yaml::from_value(cfg.clone())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Unknown configuration format"))?;
+        let dirs = dirs.into_iter()
+            .map(|dir| expand_home(&dir))
+            .collect::<Vec<_>>();
+
+        if dirs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing directories."));
+        }
+        if dirs.iter().any(|dir| dir.is_relative()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Directories must be absolute."));
+        }
+
+        Ok(Self::new(dirs, shells))
+    }
+}
+
+impl Trigger for CwdTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let shells = self.shells.clone();
+        let dirs = self.dirs.clone();
+
+        let reports = self.shells.subscribe()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Shell registry was closed."));
+        let prune = match Interval::new(Duration::from_secs(PRUNE_INTERVAL), &handle) {
+            Ok(interval) => {
+                let shells = self.shells.clone();
+                interval.map(move |_| shells.prune())
+            },
+            Err(err) => return Box::new(stream::once(Err(err))),
+        };
+
+        // Pruning notifies about exited shells through the subscription,
+        // so its own items can be skipped.
+        let stream = stream::once(Ok(()))
+            .chain(reports.select(prune.filter(|_| false)))
+            .map(move |_| {
+                shells.cwds()
+                    .iter()
+                    .any(|cwd| dirs.iter().any(|dir| cwd.starts_with(dir)))
+            });
+
+        Box::new(changes(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn load_cfg() {
+        let cfg = serde_yaml::from_str("[/srv/project, ~/src/runtext]").unwrap();
+        let trigger = CwdTrigger::from_config(&cfg, Shells::new()).unwrap();
+        assert_eq!(trigger.dirs[0], PathBuf::from("/srv/project"));
+        assert_eq!(trigger.dirs[1], expand_home("~/src/runtext"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        CwdTrigger::from_config(&Value::String("src".to_owned()), Shells::new()).unwrap();
+    }
+
+    #[test]
+    fn follows_shells() {
+        let core = Core::new().unwrap();
+        let shells = Shells::new();
+
+        let mut trigger = CwdTrigger::new(vec![PathBuf::from("/srv/project")], shells.clone());
+        let stream = trigger.listen(core.handle());
+
+        shells.report(process::id(), PathBuf::from("/srv/project/src"));
+        let (activity, stream) = stream.into_future().wait().map_err(|(err, _)| err).unwrap();
+        assert_eq!(activity, Some(Activity::Active));
+
+        shells.report(process::id(), PathBuf::from("/srv/project-old"));
+        let (activity, _) = stream.into_future().wait().map_err(|(err, _)| err).unwrap();
+        assert_eq!(activity, Some(Activity::Inactive));
+    }
+}
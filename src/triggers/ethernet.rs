@@ -0,0 +1,326 @@
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use futures::future;
+use futures::prelude::*;
+use futures::stream;
+use libc;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+use tokio_process::CommandExt;
+
+use triggers::{changes, Activity, Trigger};
+use triggers::fd::FdEvents;
+use triggers::lines::ChildLines;
+
+pub const TRIGGER_NAME: &'static str = "ethernet";
+
+/// The netlink multicast group for link changes, `RTMGRP_LINK`.
+const RTMGRP_LINK: u32 = 1;
+
+/// An evidence source that is active while an ethernet interface has
+/// a carrier, e.g. while the laptop is docked at the desk.
+///
+/// Optionally the link partner has to announce a specific system name via
+/// LLDP, which distinguishes desks that share a subnet with other rooms.
+/// Link changes are picked up from rtnetlink notifications and neighbor
+/// changes from `lldpctl watch`, nothing is polled.
+#[derive(Clone, Debug)]
+pub struct EthernetTrigger {
+    interface: String,
+    lldp: Option<String>,
+    lldpctl: PathBuf,
+    sysfs_root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct EthernetConfig {
+    interface: String,
+    lldp: Option<String>,
+    lldpctl: Option<String>,
+    sysfs_root: Option<String>,
+}
+
+impl EthernetTrigger {
+    pub fn new(interface: String, lldp: Option<String>) -> Self {
+        EthernetTrigger {
+            interface,
+            lldp,
+            lldpctl: PathBuf::from("lldpctl"),
+            sysfs_root: PathBuf::from("/sys/class/net"),
+        }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        let cfg = match *cfg {
+            Value::String(ref interface) => EthernetConfig {
+                interface: interface.clone(),
+                lldp: None,
+                lldpctl: None,
+                sysfs_root: None,
+            },
+            _ => serde_yaml::from_value(cfg.clone())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid ethernet configuration: {}", e)))?,
+        };
+        if cfg.interface.trim().is_empty() || cfg.interface.contains('/') {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid ethernet interface name."));
+        }
+
+        let mut trigger = Self::new(cfg.interface, cfg.lldp);
+        if let Some(lldpctl) = cfg.lldpctl {
+            trigger.lldpctl = PathBuf::from(lldpctl);
+        }
+        if let Some(sysfs_root) = cfg.sysfs_root {
+            trigger.sysfs_root = PathBuf::from(sysfs_root);
+        }
+
+        Ok(trigger)
+    }
+
+    /// Checks whether the interface is up and, if required, connected
+    /// to the right link partner.
+    fn is_connected(&self, handle: &Handle) -> Box<Future<Item = bool, Error = io::Error>> {
+        if !self.has_carrier() {
+            return Box::new(future::ok(false));
+        }
+
+        match self.lldp {
+            Some(ref name) => {
+                let name = name.clone();
+                Box::new(self.lldp_neighbors(handle).map(move |neighbors| neighbors.contains(&name)))
+            },
+            None => Box::new(future::ok(true)),
+        }
+    }
+
+    /// Checks the carrier and operational state of the interface.
+    ///
+    /// Reading the carrier of an interface that is administratively down
+    /// fails, as does reading from a missing interface, both count as
+    /// disconnected.
+    fn has_carrier(&self) -> bool {
+        let read = |name| fs::read_to_string(self.sysfs_root.join(&self.interface).join(name))
+            .map(|s| s.trim().to_owned())
+            .unwrap_or_default();
+
+        read("carrier") == "1" && read("operstate") == "up"
+    }
+
+    /// Gets the system names of the LLDP neighbors on the interface.
+    ///
+    /// If `lldpctl` fails, e.g. because `lldpd` isn't running (yet), there
+    /// are no neighbors.
+    fn lldp_neighbors(&self, handle: &Handle) -> Box<Future<Item = Vec<String>, Error = io::Error>> {
+        let interface = self.interface.clone();
+        let neighbors = Command::new(&self.lldpctl)
+            .args(&["-f", "keyvalue", &self.interface])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output_async_with_handle(handle.new_tokio_handle())
+            .and_then(|output| if output.status.success() {
+                Ok(parse_neighbors(&String::from_utf8_lossy(&output.stdout)))
+            } else {
+                Err(io::Error::new(io::ErrorKind::Other, format!("lldpctl exited with {}", output.status)))
+            })
+            .or_else(move |err| {
+                eprintln!("Could not list the LLDP neighbors on '{}': {}.", interface, err);
+                Ok(Vec::new())
+            });
+
+        Box::new(neighbors)
+    }
+
+    /// Gets the link changes of the interface and, if required, changes
+    /// of its LLDP neighbors.
+    ///
+    /// If the neighbors can't be watched, e.g. because `lldpd` isn't
+    /// installed, only link changes are followed.
+    fn events(&self, handle: &Handle) -> io::Result<Box<Stream<Item = (), Error = io::Error>>> {
+        let links = link_events(handle)?.map(|_| ());
+        if self.lldp.is_none() {
+            return Ok(Box::new(links));
+        }
+
+        let watch = ChildLines::spawn(
+            Command::new(&self.lldpctl).args(&["-f", "keyvalue", "watch"]),
+            handle,
+        );
+        match watch {
+            // Each neighbor change is printed as a bunch of key=value lines
+            // followed by an empty one.
+            Ok(neighbors) => {
+                let updates = neighbors.filter(|line| line.trim().is_empty()).map(|_| ());
+                Ok(Box::new(links.select(updates)))
+            },
+            Err(err) => {
+                eprintln!("Could not watch the LLDP neighbors on '{}', following link changes only: {}.", self.interface, err);
+                Ok(Box::new(links))
+            },
+        }
+    }
+}
+
+impl Trigger for EthernetTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let trigger = self.clone();
+
+        match self.events(&handle) {
+            Ok(events) => {
+                let stream = stream::once(Ok(()))
+                    .chain(events)
+                    .and_then(move |_| trigger.is_connected(&handle));
+
+                Box::new(changes(stream))
+            },
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+/// Opens a netlink socket receiving notifications about link changes.
+fn link_events(handle: &Handle) -> io::Result<FdEvents> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let events = FdEvents::new(fd, handle)?;
+
+    let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = RTMGRP_LINK;
+    let res = unsafe {
+        libc::bind(
+            fd,
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(events)
+}
+
+/// Parses the system names out of `lldpctl -f keyvalue` output
+/// like `lldp.eth0.chassis.name=desk-switch-3`.
+fn parse_neighbors(output: &str) -> Vec<String> {
+    output.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.ends_with(".chassis.name") => Some(value.trim().to_owned()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    fn fake_interface(name: &str, carrier: &str, operstate: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("runtext-ethernet-{}-{}", name, process::id()));
+        fs::create_dir_all(root.join(name)).unwrap();
+        File::create(root.join(name).join("carrier")).unwrap()
+            .write_all(carrier.as_bytes()).unwrap();
+        File::create(root.join(name).join("operstate")).unwrap()
+            .write_all(operstate.as_bytes()).unwrap();
+
+        root
+    }
+
+    #[test]
+    fn load_cfg() {
+        let trigger = EthernetTrigger::from_config(&Value::String("enp0s31f6".to_owned())).unwrap();
+        assert_eq!(trigger.interface, "enp0s31f6");
+        assert_eq!(trigger.lldp, None);
+
+        let cfg = serde_yaml::from_str("{ interface: eth0, lldp: desk-switch-3 }").unwrap();
+        let trigger = EthernetTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.lldp, Some("desk-switch-3".to_owned()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        let cfg = serde_yaml::from_str("{ interface: ../eth0 }").unwrap();
+        EthernetTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail_short() {
+        EthernetTrigger::from_config(&Value::String("".to_owned())).unwrap();
+    }
+
+    #[test]
+    fn carrier() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let up = fake_interface("eth0", "1\n", "up\n");
+        let down = fake_interface("eth1", "0\n", "down\n");
+
+        let mut trigger = EthernetTrigger::new("eth0".to_owned(), None);
+        trigger.sysfs_root = up.clone();
+        assert!(core.run(trigger.is_connected(&handle)).unwrap());
+
+        trigger.interface = "eth1".to_owned();
+        trigger.sysfs_root = down.clone();
+        assert!(!core.run(trigger.is_connected(&handle)).unwrap());
+
+        trigger.interface = "missing0".to_owned();
+        assert!(!core.run(trigger.is_connected(&handle)).unwrap());
+
+        fs::remove_dir_all(up).unwrap();
+        fs::remove_dir_all(down).unwrap();
+    }
+
+    #[test]
+    fn lldp_neighbor() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let root = fake_interface("eth2", "1\n", "up\n");
+        let lldpctl = root.join("lldpctl");
+        let mut script = File::create(&lldpctl).unwrap();
+        script.write_all(b"#!/bin/sh\n\
+            echo 'lldp.eth2.via=LLDP'\n\
+            echo 'lldp.eth2.chassis.name=desk-switch-3'\n").unwrap();
+        drop(script);
+        fs::set_permissions(&lldpctl, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut trigger = EthernetTrigger::new("eth2".to_owned(), Some("desk-switch-3".to_owned()));
+        trigger.sysfs_root = root.clone();
+        trigger.lldpctl = lldpctl;
+        assert!(core.run(trigger.is_connected(&handle)).unwrap());
+
+        trigger.lldp = Some("meeting-room".to_owned());
+        assert!(!core.run(trigger.is_connected(&handle)).unwrap());
+
+        // A missing or failing lldpctl doesn't stop the trigger.
+        trigger.lldp = Some("desk-switch-3".to_owned());
+        trigger.lldpctl = root.join("missing");
+        assert!(!core.run(trigger.is_connected(&handle)).unwrap());
+        assert!(trigger.events(&handle).is_ok());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+//! Reading from raw file descriptors without blocking the event loop, for
+//! evidence sources built upon kernel interfaces like netlink or inotify.
+
+use std::io;
+use std::os::unix::io::RawFd;
+
+use futures::prelude::*;
+use libc;
+use mio::{Evented, Poll as MioPoll, PollOpt, Ready, Token};
+use mio::unix::EventedFd;
+use tokio_core::reactor::{Handle, PollEvented};
+
+/// The maximum size of a single read, large enough for a netlink message
+/// or a batch of inotify events.
+const BUF_SIZE: usize = 8192;
+
+/// A file descriptor that is closed when dropped.
+#[derive(Debug)]
+struct OwnedFd(RawFd);
+
+/// A stream of the chunks of data read from a non-blocking file descriptor.
+///
+/// Every item is the result of a single `read`, so for datagram-based
+/// descriptors like netlink sockets each item is one datagram.
+pub struct FdEvents {
+    io: PollEvented<OwnedFd>,
+}
+
+impl FdEvents {
+    /// Takes ownership of the given file descriptor and registers it
+    /// with the event loop.
+    ///
+    /// The descriptor must have been opened in non-blocking mode. It is
+    /// closed, even if registering fails.
+    pub fn new(fd: RawFd, handle: &Handle) -> io::Result<Self> {
+        let io = PollEvented::new(OwnedFd(fd), handle)?;
+        Ok(FdEvents { io })
+    }
+}
+
+impl Stream for FdEvents {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Async::NotReady = self.io.poll_read() {
+            return Ok(Async::NotReady);
+        }
+
+        let mut buf = vec![0; BUF_SIZE];
+        let res = unsafe {
+            libc::read(self.io.get_ref().0, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+        };
+
+        match res {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    self.io.need_read();
+                    Ok(Async::NotReady)
+                } else {
+                    Err(err)
+                }
+            },
+            0 => Ok(Async::Ready(None)),
+            len => {
+                buf.truncate(len as usize);
+                Ok(Async::Ready(Some(buf)))
+            },
+        }
+    }
+}
+
+impl Evented for OwnedFd {
+    fn register(&self, poll: &MioPoll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.0).register(poll, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &MioPoll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.0).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &MioPoll) -> io::Result<()> {
+        EventedFd(&self.0).deregister(poll)
+    }
+}
+
+impl Drop for OwnedFd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn reads_pipe() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        unsafe { libc::fcntl(fds[0], libc::F_SETFL, libc::O_NONBLOCK); }
+
+        let mut core = Core::new().unwrap();
+        let events = FdEvents::new(fds[0], &core.handle()).unwrap();
+
+        let msg = b"link changed";
+        unsafe {
+            libc::write(fds[1], msg.as_ptr() as *const libc::c_void, msg.len());
+            libc::close(fds[1]);
+        }
+
+        let chunks = core.run(events.collect()).unwrap();
+        assert_eq!(chunks, vec![msg.to_vec()]);
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use futures::future;
+use futures::prelude::*;
+use futures::stream;
+use libc;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use triggers::{Activity, Trigger};
+
+pub const TRIGGER_NAME: &'static str = "host";
+
+/// An evidence source that is active on specific machines only.
+///
+/// This allows sharing a single configuration across machines, e.g. via
+/// a dotfiles repository. Machines are identified by their hostname,
+/// machine ID, fields of `/etc/os-release` or environment variables, all
+/// given conditions have to match.
+///
+/// The identity of a machine doesn't change while the daemon runs, so it
+/// is only checked once.
+#[derive(Clone, Debug, Default)]
+pub struct HostTrigger {
+    hostname: Option<String>,
+    machine_id: Option<String>,
+    os_release: BTreeMap<String, String>,
+    env: BTreeMap<String, String>,
+    root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct HostConfig {
+    hostname: Option<String>,
+    machine_id: Option<String>,
+    #[serde(default)]
+    os_release: BTreeMap<String, String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+impl HostTrigger {
+    /// Creates a new `HostTrigger` matching the given hostname.
+    pub fn new(hostname: String) -> Self {
+        HostTrigger {
+            hostname: Some(hostname),
+            root: PathBuf::from("/"),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Value::String(ref hostname) = *cfg {
+            return Ok(Self::new(hostname.clone()));
+        }
+
+        let cfg: HostConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid host configuration: {}", e)))?;
+        if cfg.hostname.is_none() && cfg.machine_id.is_none() && cfg.os_release.is_empty() && cfg.env.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Missing hostname, machine_id, os_release or env condition.",
+            ));
+        }
+
+        Ok(HostTrigger {
+            hostname: cfg.hostname,
+            machine_id: cfg.machine_id,
+            os_release: cfg.os_release,
+            env: cfg.env,
+            root: PathBuf::from("/"),
+        })
+    }
+
+    /// Checks whether this is the machine the trigger is looking for.
+    fn matches(&self) -> io::Result<bool> {
+        if let Some(ref hostname) = self.hostname {
+            if &self.read("etc/hostname")? != hostname && &current_hostname()? != hostname {
+                return Ok(false);
+            }
+        }
+
+        if let Some(ref machine_id) = self.machine_id {
+            if &self.read("etc/machine-id")? != machine_id {
+                return Ok(false);
+            }
+        }
+
+        if !self.os_release.is_empty() {
+            let os_release = parse_os_release(&self.read("etc/os-release")?);
+            let matches = self.os_release.iter()
+                .all(|(key, value)| os_release.get(key) == Some(value));
+            if !matches {
+                return Ok(false);
+            }
+        }
+
+        Ok(self.env.iter().all(|(key, value)| env::var(key).ok().as_ref() == Some(value)))
+    }
+
+    /// Reads a file below the root directory, which is empty if it
+    /// doesn't exist.
+    fn read(&self, path: &str) -> io::Result<String> {
+        match fs::read_to_string(self.root.join(path)) {
+            Ok(content) => Ok(content.trim().to_owned()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Trigger for HostTrigger {
+    fn listen(&mut self, _: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        // Never end the stream, other triggers of the context may still
+        // be changing.
+        let forever = future::empty().into_stream();
+
+        match self.matches() {
+            Ok(true) => Box::new(stream::once(Ok(Activity::Active)).chain(forever)),
+            Ok(false) => Box::new(forever),
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+/// Gets the hostname from the kernel.
+fn current_hostname() -> io::Result<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// Parses the `KEY=value` lines of `/etc/os-release`.
+fn parse_os_release(content: &str) -> BTreeMap<String, String> {
+    content.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim().trim_matches(|c| c == '"' || c == '\'');
+
+            Some((key.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use std::process;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn load_cfg() {
+        let trigger = HostTrigger::from_config(&Value::String("build-01".to_owned())).unwrap();
+        assert_eq!(trigger.hostname, Some("build-01".to_owned()));
+
+        let cfg = serde_yaml::from_str("{ os_release: { ID: fedora }, env: { TEAM: infra } }").unwrap();
+        let trigger = HostTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.os_release.get("ID"), Some(&"fedora".to_owned()));
+        assert_eq!(trigger.env.get("TEAM"), Some(&"infra".to_owned()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        let cfg = serde_yaml::from_str("{ os_release: {} }").unwrap();
+        HostTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    fn parse_os_release_fields() {
+        let fields = parse_os_release("# comment\nNAME=\"Fedora Linux\"\nID=fedora\nVERSION_ID=38\n");
+        assert_eq!(fields.get("NAME"), Some(&"Fedora Linux".to_owned()));
+        assert_eq!(fields.get("ID"), Some(&"fedora".to_owned()));
+        assert_eq!(fields.get("VERSION_ID"), Some(&"38".to_owned()));
+    }
+
+    #[test]
+    fn matches_machine() {
+        let root = env::temp_dir().join(format!("runtext-host-{}", process::id()));
+        fs::create_dir_all(root.join("etc")).unwrap();
+        File::create(root.join("etc/machine-id")).unwrap()
+            .write_all(b"0123456789abcdef\n").unwrap();
+        File::create(root.join("etc/os-release")).unwrap()
+            .write_all(b"ID=debian\nVERSION_ID=\"12\"\n").unwrap();
+
+        let mut trigger = HostTrigger {
+            machine_id: Some("0123456789abcdef".to_owned()),
+            root: root.clone(),
+            ..Default::default()
+        };
+        trigger.os_release.insert("ID".to_owned(), "debian".to_owned());
+        assert!(trigger.matches().unwrap());
+
+        let mut core = Core::new().unwrap();
+        let (activity, _) = core.run(trigger.listen(core.handle()).into_future())
+            .map_err(|(err, _)| err)
+            .unwrap();
+        assert_eq!(activity, Some(Activity::Active));
+
+        trigger.os_release.insert("VERSION_ID".to_owned(), "11".to_owned());
+        assert!(!trigger.matches().unwrap());
+
+        trigger.os_release.clear();
+        trigger.env.insert("RUNTEXT_HOST_TEST_MISSING".to_owned(), "1".to_owned());
+        assert!(!trigger.matches().unwrap());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
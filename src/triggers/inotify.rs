@@ -0,0 +1,64 @@
+//! Watching files and directories for changes with inotify.
+
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use libc;
+use tokio_core::reactor::Handle;
+
+use triggers::fd::FdEvents;
+
+/// Watches the given paths for the events in the respective masks,
+/// e.g. `libc::IN_CREATE | libc::IN_DELETE`.
+///
+/// Every item of the returned stream is a batch of raw inotify events.
+/// Evidence sources usually don't care about the details and just check
+/// their condition again.
+pub fn watch(paths: &[(&Path, u32)], handle: &Handle) -> io::Result<FdEvents> {
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let events = FdEvents::new(fd, handle)?;
+
+    for &(path, mask) in paths {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains a NUL byte."))?;
+        if unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::process;
+
+    use futures::prelude::*;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn notices_new_files() {
+        let dir = env::temp_dir().join(format!("runtext-inotify-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut core = Core::new().unwrap();
+        let events = watch(&[(&dir, libc::IN_CREATE)], &core.handle()).unwrap();
+        File::create(dir.join("new")).unwrap();
+
+        let (batch, _) = core.run(events.into_future())
+            .map_err(|(err, _)| err)
+            .unwrap();
+        assert!(batch.is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
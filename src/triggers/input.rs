@@ -0,0 +1,283 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use futures::future;
+use futures::prelude::*;
+use futures::stream;
+use libc;
+use regex::Regex;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use triggers::{changes, inotify, Activity, Trigger};
+
+pub const TRIGGER_NAME: &'static str = "input_device";
+
+/// An evidence source that is active while an input device is attached,
+/// e.g. an external keyboard.
+///
+/// Devices are matched by name against the entries of `/dev/input/by-id`
+/// and the device names in `/proc/bus/input/devices`, or by their USB
+/// vendor and product ID. `/dev/input` is watched for devices coming and
+/// going.
+#[derive(Clone, Debug)]
+pub struct InputTrigger {
+    name: Option<Regex>,
+    id: Option<(u16, u16)>,
+    dev_root: PathBuf,
+    proc_devices: PathBuf,
+}
+
+/// An input device listed in `/proc/bus/input/devices`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Device {
+    name: String,
+    vendor: u16,
+    product: u16,
+}
+
+#[derive(Debug, Deserialize)]
+struct InputConfig {
+    name: Option<String>,
+    id: Option<String>,
+    dev_root: Option<String>,
+    proc_devices: Option<String>,
+}
+
+impl InputTrigger {
+    /// Creates a new `InputTrigger` for the devices whose names match
+    /// the given pattern.
+    pub fn new(name: &str) -> io::Result<Self> {
+        Ok(InputTrigger {
+            name: Some(Self::pattern(name)?),
+            id: None,
+            dev_root: PathBuf::from("/dev/input"),
+            proc_devices: PathBuf::from("/proc/bus/input/devices"),
+        })
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Value::String(ref name) = *cfg {
+            return Self::new(name);
+        }
+
+        let cfg: InputConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid input_device configuration: {}", e)))?;
+        if cfg.name.is_none() && cfg.id.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing input device name or ID."));
+        }
+
+        Ok(InputTrigger {
+            name: cfg.name.as_ref().map(|n| Self::pattern(n)).map_or(Ok(None), |r| r.map(Some))?,
+            id: cfg.id.as_ref().map(|id| parse_id(id)).map_or(Ok(None), |r| r.map(Some))?,
+            dev_root: PathBuf::from(cfg.dev_root.unwrap_or("/dev/input".to_owned())),
+            proc_devices: PathBuf::from(cfg.proc_devices.unwrap_or("/proc/bus/input/devices".to_owned())),
+        })
+    }
+
+    fn pattern(name: &str) -> io::Result<Regex> {
+        Regex::new(name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid input device pattern: {}", e)))
+    }
+
+    /// Checks whether a matching device is attached.
+    fn is_present(&self) -> io::Result<bool> {
+        let devices = match fs::read_to_string(&self.proc_devices) {
+            Ok(content) => parse_devices(&content),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let in_proc = devices.iter().any(|device| {
+            self.name.as_ref().map_or(true, |name| name.is_match(&device.name)) &&
+                self.id.map_or(true, |id| id == (device.vendor, device.product))
+        });
+        if in_proc {
+            return Ok(true);
+        }
+
+        // The by-id links only carry names, not IDs.
+        match (self.name.as_ref(), self.id) {
+            (Some(name), None) => Ok(list_dir(&self.dev_root.join("by-id"))?.iter().any(|link| name.is_match(link))),
+            _ => Ok(false),
+        }
+    }
+
+    fn watch(&self, handle: &Handle) -> io::Result<Box<Stream<Item = (), Error = io::Error>>> {
+        let by_id = self.dev_root.join("by-id");
+        let mask = libc::IN_CREATE | libc::IN_DELETE;
+
+        // by-id is created by udev along with the first device and may be
+        // missing, new devices always show up in the root as well though.
+        let mut paths = vec![(self.dev_root.as_path(), mask)];
+        if by_id.is_dir() {
+            paths.push((by_id.as_path(), mask));
+        }
+
+        Ok(Box::new(inotify::watch(&paths, handle)?.map(|_| ())))
+    }
+}
+
+impl Trigger for InputTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let trigger = self.clone();
+
+        match self.watch(&handle) {
+            Ok(events) => {
+                let stream = stream::once(Ok(()))
+                    .chain(events)
+                    .and_then(move |_| trigger.is_present());
+
+                Box::new(changes(stream))
+            },
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+/// Parses a USB ID like `046d:c31c`.
+fn parse_id(id: &str) -> io::Result<(u16, u16)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid input device ID '{}'.", id));
+    let mut parts = id.trim().splitn(2, ':');
+
+    let vendor = parts.next().and_then(|v| u16::from_str_radix(v, 16).ok()).ok_or_else(&invalid)?;
+    let product = parts.next().and_then(|p| u16::from_str_radix(p, 16).ok()).ok_or_else(&invalid)?;
+    Ok((vendor, product))
+}
+
+/// Parses the device list in `/proc/bus/input/devices`.
+///
+/// Devices are separated by empty lines, e.g.:
+///
+/// ```text
+/// I: Bus=0003 Vendor=046d Product=c31c Version=0110
+/// N: Name="Logitech USB Keyboard"
+/// ```
+fn parse_devices(content: &str) -> Vec<Device> {
+    let mut devices = Vec::new();
+    let mut current: Option<Device> = None;
+
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            devices.extend(current.take());
+            continue;
+        }
+
+        let device = current.get_or_insert_with(Device::default);
+        if line.starts_with("I: ") {
+            for field in line[3..].split_whitespace() {
+                let mut parts = field.splitn(2, '=');
+                let value = |v: Option<&str>| v.and_then(|v| u16::from_str_radix(v, 16).ok()).unwrap_or(0);
+                match parts.next() {
+                    Some("Vendor") => device.vendor = value(parts.next()),
+                    Some("Product") => device.product = value(parts.next()),
+                    _ => {},
+                }
+            }
+        } else if line.starts_with("N: Name=") {
+            device.name = line["N: Name=".len()..].trim_matches('"').to_owned();
+        }
+    }
+    devices.extend(current);
+
+    devices
+}
+
+/// Lists the names of the entries of a directory, which is empty if
+/// the directory doesn't exist.
+fn list_dir(path: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    entries.map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::process;
+    use std::time::Duration;
+
+    use tokio_core::reactor::{Core, Timeout};
+
+    use super::*;
+
+    const DEVICES: &'static str = "I: Bus=0019 Vendor=0000 Product=0001 Version=0000
+N: Name=\"Power Button\"
+P: Phys=LNXPWRBN/button/input0
+
+I: Bus=0003 Vendor=046d Product=c31c Version=0110
+N: Name=\"Logitech USB Keyboard\"
+H: Handlers=sysrq kbd event4 leds
+";
+
+    #[test]
+    fn load_cfg() {
+        let trigger = InputTrigger::from_config(&Value::String("(?i)keyboard".to_owned())).unwrap();
+        assert!(trigger.name.is_some());
+
+        let cfg = serde_yaml::from_str("{ id: '046d:c31c' }").unwrap();
+        let trigger = InputTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.id, Some((0x046d, 0xc31c)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        let cfg = serde_yaml::from_str("{ id: 'logitech' }").unwrap();
+        InputTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    fn parse_proc_devices() {
+        let devices = parse_devices(DEVICES);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[1], Device {
+            name: "Logitech USB Keyboard".to_owned(),
+            vendor: 0x046d,
+            product: 0xc31c,
+        });
+    }
+
+    #[test]
+    fn follows_devices() {
+        let root = env::temp_dir().join(format!("runtext-input-{}", process::id()));
+        fs::create_dir_all(root.join("input/by-id")).unwrap();
+        File::create(root.join("devices")).unwrap()
+            .write_all(DEVICES.as_bytes()).unwrap();
+
+        let mut trigger = InputTrigger::new("^usb-Kinesis_Advantage2.*-event-kbd$").unwrap();
+        trigger.dev_root = root.join("input");
+        trigger.proc_devices = root.join("devices");
+        assert!(!trigger.is_present().unwrap());
+
+        let mut by_id = trigger.clone();
+        by_id.name = None;
+        by_id.id = Some((0x046d, 0xc31c));
+        assert!(by_id.is_present().unwrap());
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let stream = trigger.listen(handle.clone());
+
+        let link = root.join("input/by-id/usb-Kinesis_Advantage2_Keyboard-event-kbd");
+        let plug = Timeout::new(Duration::from_millis(50), &handle).unwrap()
+            .map(move |_| { File::create(link).unwrap(); })
+            .map_err(|_| ());
+        handle.spawn(plug);
+
+        let (activity, _) = core.run(stream.into_future())
+            .map_err(|(err, _)| err)
+            .unwrap();
+        assert_eq!(activity, Some(Activity::Active));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
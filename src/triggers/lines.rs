@@ -0,0 +1,64 @@
+//! Streaming the output of long-running child processes, for evidence
+//! sources built upon tools like `xprop -spy` or `pactl subscribe`.
+
+use std::io::{self, BufReader};
+use std::process::{Command, Stdio};
+
+use futures::prelude::*;
+use tokio_core::reactor::Handle;
+use tokio_io::io::{lines, Lines};
+use tokio_process::{Child, ChildStdout, CommandExt};
+
+/// A stream of the lines a child process prints on its stdout.
+///
+/// The child process is killed when the stream is dropped.
+#[derive(Debug)]
+pub struct ChildLines {
+    _child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl ChildLines {
+    /// Spawns the given command and starts reading its output.
+    pub fn spawn(cmd: &mut Command, handle: &Handle) -> io::Result<Self> {
+        let mut child = cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn_async_with_handle(handle.new_tokio_handle())?;
+        let stdout = child.stdout()
+            .take()
+            .ok_or(io::Error::new(io::ErrorKind::Other, "Missing stdout of child process."))?;
+
+        Ok(ChildLines {
+            _child: child,
+            lines: lines(BufReader::new(stdout)),
+        })
+    }
+}
+
+impl Stream for ChildLines {
+    type Item = String;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.lines.poll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn reads_lines() {
+        let mut core = Core::new().unwrap();
+
+        let mut cmd = Command::new("printf");
+        cmd.arg("a\\nb\\n");
+        let stream = ChildLines::spawn(&mut cmd, &core.handle()).unwrap();
+
+        let lines = core.run(stream.collect()).unwrap();
+        assert_eq!(lines, vec!["a", "b"]);
+    }
+}
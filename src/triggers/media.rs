@@ -0,0 +1,216 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use futures::future;
+use futures::prelude::*;
+use humantime;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+use tokio_process::CommandExt;
+
+use context::config_error;
+use multi::Multi;
+use triggers::{Activity, Trigger};
+use triggers::poll::PollStream;
+
+pub const TRIGGER_NAME: &'static str = "media_in_use";
+
+/// An evidence source that is active while a camera or microphone
+/// is being used by any process.
+///
+/// Cameras are found by scanning the open file descriptors in `/proc`
+/// for `/dev/video*` devices, microphones by asking PulseAudio (or
+/// PipeWire's PulseAudio emulation) for recording streams.
+#[derive(Clone, Debug)]
+pub struct MediaTrigger {
+    devices: Vec<Device>,
+    interval: Duration,
+    pactl: PathBuf,
+    proc_root: PathBuf,
+}
+
+/// The kinds of media devices that can be watched.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Device {
+    Camera,
+    Microphone,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaConfig {
+    devices: Option<Multi<Device>>,
+    interval: Option<String>,
+    pactl: Option<String>,
+    proc_root: Option<String>,
+}
+
+impl MediaTrigger {
+    pub fn new(devices: Vec<Device>) -> Self {
+        MediaTrigger {
+            devices,
+            interval: Duration::from_secs(2),
+            pactl: PathBuf::from("pactl"),
+            proc_root: PathBuf::from("/proc"),
+        }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Ok(devices) = serde_yaml::from_value::<Multi<Device>>(cfg.clone()) {
+            return Ok(Self::new(devices.into_iter().collect()));
+        }
+
+        let cfg: MediaConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| config_error(TRIGGER_NAME, e))?;
+        let devices = cfg.devices
+            .map(|d| d.into_iter().collect())
+            .unwrap_or_else(|| vec![Device::Camera, Device::Microphone]);
+
+        let mut trigger = Self::new(devices);
+        if let Some(interval) = cfg.interval {
+            trigger.interval = humantime::parse_duration(&interval).map_err(|e| config_error(TRIGGER_NAME, e))?;
+        }
+        if let Some(pactl) = cfg.pactl {
+            trigger.pactl = PathBuf::from(pactl);
+        }
+        if let Some(proc_root) = cfg.proc_root {
+            trigger.proc_root = PathBuf::from(proc_root);
+        }
+
+        Ok(trigger)
+    }
+
+    /// Checks whether any of the watched devices is in use.
+    fn is_in_use(&self, handle: &Handle) -> Box<Future<Item = bool, Error = io::Error>> {
+        if self.devices.contains(&Device::Camera) && is_camera_in_use(&self.proc_root) {
+            return Box::new(future::ok(true));
+        }
+
+        if self.devices.contains(&Device::Microphone) {
+            is_microphone_in_use(&self.pactl, handle)
+        } else {
+            Box::new(future::ok(false))
+        }
+    }
+}
+
+impl Trigger for MediaTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let trigger = self.clone();
+        let h = handle.clone();
+
+        match PollStream::new(self.interval, &handle, move || trigger.is_in_use(&h)) {
+            Ok(stream) => Box::new(stream),
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+/// Checks whether any process has a video device open.
+///
+/// Processes whose file descriptors cannot be read (e.g. because they
+/// belong to other users) are skipped.
+fn is_camera_in_use(proc_root: &Path) -> bool {
+    let processes = match fs::read_dir(proc_root) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    processes.filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().chars().all(|c| c.is_digit(10)))
+        .filter_map(|entry| fs::read_dir(entry.path().join("fd")).ok())
+        .flat_map(|fds| fds.filter_map(Result::ok))
+        .filter_map(|fd| fs::read_link(fd.path()).ok())
+        .any(|target| target.to_string_lossy().starts_with("/dev/video"))
+}
+
+/// Checks whether any application is recording from an audio source.
+fn is_microphone_in_use(pactl: &Path, handle: &Handle) -> Box<Future<Item = bool, Error = io::Error>> {
+    let output = Command::new(pactl)
+        .args(&["list", "short", "source-outputs"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output_async_with_handle(handle.new_tokio_handle());
+
+    Box::new(output.then(|output| match output {
+        Ok(ref out) if out.status.success() => {
+            Ok(String::from_utf8_lossy(&out.stdout)
+                .lines()
+                .any(|l| !l.trim().is_empty()))
+        },
+        _ => Ok(false),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("runtext-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn load_cfg() {
+        let trigger = MediaTrigger::from_config(&Value::String("camera".to_owned())).unwrap();
+        assert_eq!(trigger.devices, vec![Device::Camera]);
+
+        let cfg = serde_yaml::from_str("{ pactl: /opt/pactl, interval: 5s }").unwrap();
+        let trigger = MediaTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.devices, vec![Device::Camera, Device::Microphone]);
+        assert_eq!(trigger.pactl, PathBuf::from("/opt/pactl"));
+        assert_eq!(trigger.interval, Duration::from_secs(5));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        MediaTrigger::from_config(&Value::String("speaker".to_owned())).unwrap();
+    }
+
+    #[test]
+    fn camera_in_use() {
+        let root = temp_dir("proc");
+        fs::create_dir_all(root.join("self")).unwrap();
+        fs::create_dir_all(root.join("42/fd")).unwrap();
+        symlink("/dev/null", root.join("42/fd/0")).unwrap();
+        assert!(!is_camera_in_use(&root));
+
+        fs::create_dir_all(root.join("1337/fd")).unwrap();
+        symlink("/dev/video0", root.join("1337/fd/7")).unwrap();
+        assert!(is_camera_in_use(&root));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn microphone_in_use() {
+        let dir = temp_dir("pactl");
+        let pactl = dir.join("pactl");
+        let mut script = File::create(&pactl).unwrap();
+        script.write_all(b"#!/bin/sh\necho '12\t1\t3\tprotocol-native.c\tfloat32le 1ch 48000Hz'\n").unwrap();
+        drop(script);
+        fs::set_permissions(&pactl, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        assert!(core.run(is_microphone_in_use(&pactl, &handle)).unwrap());
+        assert!(!core.run(is_microphone_in_use(&dir.join("nonexisting-pactl"), &handle)).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
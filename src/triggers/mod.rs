@@ -3,7 +3,38 @@ use std::io;
 use futures::prelude::*;
 use tokio_core::reactor::Handle;
 
+#[cfg(unix)]
+pub mod audio;
+pub mod calendar;
+pub mod context;
+#[cfg(unix)]
+pub mod cwd;
+#[cfg(target_os = "linux")]
+pub mod ethernet;
+#[cfg(unix)]
+pub mod fd;
+#[cfg(unix)]
+pub mod host;
+#[cfg(target_os = "linux")]
+pub mod inotify;
+#[cfg(target_os = "linux")]
+pub mod input;
+pub mod lines;
+#[cfg(unix)]
+pub mod media;
+pub mod poll;
+pub mod schedule;
+#[cfg(unix)]
+pub mod signal;
+pub mod sun;
+#[cfg(target_os = "linux")]
+pub mod timezone;
+#[cfg(target_os = "linux")]
+pub mod users;
 pub mod wifi;
+pub mod wifi_scan;
+#[cfg(unix)]
+pub mod window;
 
 /// A context activity change
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -20,4 +51,58 @@ pub trait Trigger {
     /// Start listening for the context and dispatch signals
     /// whenever the context is entered or left.
     fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>>;
-}
\ No newline at end of file
+}
+
+/// A stream of activity changes derived from a stream telling whether
+/// a context is active, see `changes`.
+#[derive(Debug)]
+pub struct Changes<S> {
+    inner: S,
+    was_active: bool,
+}
+
+/// Converts a stream telling whether a context is active into a stream of
+/// activity changes, leaving out repetitions.
+///
+/// The context is assumed to be inactive initially.
+pub fn changes<S: Stream<Item = bool>>(stream: S) -> Changes<S> {
+    Changes {
+        inner: stream,
+        was_active: false,
+    }
+}
+
+impl<S: Stream<Item = bool>> Stream for Changes<S> {
+    type Item = Activity;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            match try_ready!(self.inner.poll()) {
+                Some(is_active) if is_active != self.was_active => {
+                    self.was_active = is_active;
+
+                    let activity = if is_active { Activity::Active } else { Activity::Inactive };
+                    return Ok(Async::Ready(Some(activity)));
+                },
+                Some(_) => continue,
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    #[test]
+    fn changes_leave_out_repetitions() {
+        let input = stream::iter_ok::<_, ()>(vec![false, true, true, false, false, true]);
+
+        let activities = changes(input).collect().wait().unwrap();
+        assert_eq!(activities, vec![Activity::Active, Activity::Inactive, Activity::Active]);
+    }
+}
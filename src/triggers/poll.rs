@@ -0,0 +1,106 @@
+//! Timer machinery for evidence sources that have to periodically check
+//! whether their context is active.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use futures::prelude::*;
+use tokio_core::reactor::{Handle, Timeout};
+
+use triggers::Activity;
+
+/// A stream of activity changes found by periodically running a check.
+///
+/// The check may complete right away or return a future, e.g. of a
+/// command it runs. The next check is due `interval` after the last one
+/// completed.
+pub struct PollStream<F, R: IntoFuture> {
+    check: F,
+    interval: Duration,
+    timeout: Timeout,
+    running: Option<R::Future>,
+    was_active: bool,
+}
+
+impl<F, R> PollStream<F, R>
+    where F: FnMut() -> R,
+          R: IntoFuture<Item = bool, Error = io::Error> {
+    /// Creates a new `PollStream` running `check` every `interval`, starting
+    /// right away.
+    ///
+    /// `check` returns whether the context is currently active.
+    pub fn new(interval: Duration, handle: &Handle, check: F) -> io::Result<Self> {
+        Ok(PollStream {
+            check,
+            interval,
+            timeout: Timeout::new(Duration::from_millis(0), handle)?,
+            running: None,
+            was_active: false,
+        })
+    }
+}
+
+impl<F, R> Stream for PollStream<F, R>
+    where F: FnMut() -> R,
+          R: IntoFuture<Item = bool, Error = io::Error> {
+    type Item = Activity;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if self.running.is_none() {
+                try_ready!(self.timeout.poll());
+                self.running = Some((self.check)().into_future());
+            }
+
+            let is_active = match self.running {
+                Some(ref mut check) => try_ready!(check.poll()),
+                None => unreachable!(),
+            };
+            self.running = None;
+            self.timeout.reset(Instant::now() + self.interval);
+
+            if is_active != self.was_active {
+                self.was_active = is_active;
+
+                let activity = if is_active { Activity::Active } else { Activity::Inactive };
+                return Ok(Async::Ready(Some(activity)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn signals_changes() {
+        let mut core = Core::new().unwrap();
+
+        let mut results = vec![true, true, false, false, true].into_iter();
+        let stream = PollStream::new(Duration::from_millis(1), &core.handle(), move || {
+            Ok(results.next().expect("Polled too often."))
+        }).unwrap();
+
+        let activities = core.run(stream.take(3).collect()).unwrap();
+        assert_eq!(activities, vec![Activity::Active, Activity::Inactive, Activity::Active]);
+    }
+
+    #[test]
+    fn waits_for_asynchronous_checks() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let mut results = vec![false, true].into_iter();
+        let stream = PollStream::new(Duration::from_millis(1), &handle.clone(), move || {
+            let is_active = results.next().expect("Polled too often.");
+            Timeout::new(Duration::from_millis(10), &handle).unwrap().map(move |_| is_active)
+        }).unwrap();
+
+        let activities = core.run(stream.take(1).collect()).unwrap();
+        assert_eq!(activities, vec![Activity::Active]);
+    }
+}
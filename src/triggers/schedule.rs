@@ -0,0 +1,137 @@
+//! Timer machinery for evidence sources whose activity follows a schedule
+//! that can be computed ahead of time, like the position of the sun.
+
+use std::io;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::prelude::*;
+use tokio_core::reactor::{Handle, Timeout};
+
+use triggers::Activity;
+
+/// The amount of seconds in a day.
+pub const DAY: i64 = 24 * 60 * 60;
+
+/// The maximum amount of seconds to wait before looking at the schedule again.
+///
+/// Timers are based on a monotonic clock, so this guards against wall clock
+/// changes and the system being suspended.
+const MAX_SLEEP: i64 = 60;
+
+/// A schedule of time spans during which a context is active.
+///
+/// All points in time are given as seconds since the unix epoch.
+pub trait Schedule {
+    /// Computes the time spans during which the context is active that
+    /// overlap the time span from `from` to `to`.
+    ///
+    /// The spans are given as pairs of start (inclusive) and end (exclusive).
+    /// They may overlap each other.
+    fn intervals(&mut self, from: i64, to: i64) -> io::Result<Vec<(i64, i64)>>;
+}
+
+/// A stream of activity changes following a `Schedule`.
+#[derive(Debug)]
+pub struct ScheduleStream<S> {
+    schedule: S,
+    timeout: Timeout,
+    was_active: bool,
+}
+
+/// Gets the current wall clock time in seconds since the unix epoch.
+pub fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(dur) => dur.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+impl<S: Schedule> ScheduleStream<S> {
+    pub fn new(schedule: S, handle: &Handle) -> io::Result<Self> {
+        Ok(ScheduleStream {
+            schedule,
+            timeout: Timeout::new(Duration::from_millis(0), handle)?,
+            was_active: false,
+        })
+    }
+
+    /// Determines whether the context is active at `now` and when the
+    /// schedule has to be looked at next.
+    fn evaluate(&mut self, now: i64) -> io::Result<(bool, i64)> {
+        let intervals = self.schedule.intervals(now - DAY, now + DAY)?;
+
+        let is_active = intervals.iter()
+            .any(|&(start, end)| start <= now && now < end);
+        let next_change = intervals.iter()
+            .flat_map(|&(start, end)| vec![start, end])
+            .filter(|&t| t > now)
+            .min()
+            .unwrap_or(now + MAX_SLEEP);
+
+        Ok((is_active, next_change))
+    }
+}
+
+impl<S: Schedule> Stream for ScheduleStream<S> {
+    type Item = Activity;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            try_ready!(self.timeout.poll());
+
+            let now = now();
+            let (is_active, next_change) = self.evaluate(now)?;
+            let sleep = (next_change - now).max(1).min(MAX_SLEEP);
+            self.timeout.reset(Instant::now() + Duration::from_secs(sleep as u64));
+
+            if is_active != self.was_active {
+                self.was_active = is_active;
+
+                let activity = if is_active { Activity::Active } else { Activity::Inactive };
+                return Ok(Async::Ready(Some(activity)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    struct Fixed(Vec<(i64, i64)>);
+
+    impl Schedule for Fixed {
+        fn intervals(&mut self, _: i64, _: i64) -> io::Result<Vec<(i64, i64)>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn evaluate() {
+        let core = Core::new().unwrap();
+        let schedule = Fixed(vec![(100, 200), (150, 300), (500, 600)]);
+        let mut stream = ScheduleStream::new(schedule, &core.handle()).unwrap();
+
+        assert_eq!(stream.evaluate(50).unwrap(), (false, 100));
+        assert_eq!(stream.evaluate(100).unwrap(), (true, 150));
+        assert_eq!(stream.evaluate(250).unwrap(), (true, 300));
+        assert_eq!(stream.evaluate(300).unwrap(), (false, 500));
+        assert_eq!(stream.evaluate(700).unwrap(), (false, 700 + MAX_SLEEP));
+    }
+
+    #[test]
+    fn signals_current_activity() {
+        let mut core = Core::new().unwrap();
+        let now = now();
+        let schedule = Fixed(vec![(now - 10, now + 100)]);
+        let stream = ScheduleStream::new(schedule, &core.handle()).unwrap();
+
+        let (activity, _) = core.run(stream.into_future())
+            .map_err(|(err, _)| err)
+            .unwrap();
+        assert_eq!(activity, Some(Activity::Active));
+    }
+}
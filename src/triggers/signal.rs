@@ -0,0 +1,170 @@
+use std::io;
+
+use futures::prelude::*;
+use libc::{self, c_int};
+use serde_yaml::Value;
+use tokio_core::reactor::Handle;
+use tokio_signal::unix::Signal;
+
+use triggers::{changes, Activity, Trigger};
+
+pub const TRIGGER_NAME: &'static str = "signal";
+
+/// The signals that may be used to control a context.
+///
+/// `SIGINT`, `SIGTERM` and friends are deliberately missing, capturing
+/// them would prevent the daemon from being shut down.
+const SIGNALS: &'static [(&'static str, c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("ALRM", libc::SIGALRM),
+    ("WINCH", libc::SIGWINCH),
+];
+
+/// An evidence source that is controlled by Unix signals sent to
+/// the daemon, e.g. via `pkill -USR1 runtext`.
+///
+/// Either one signal enters and another one leaves the context, or
+/// a single signal toggles it.
+#[derive(Debug)]
+pub struct SignalTrigger {
+    enter: c_int,
+    leave: Option<c_int>,
+}
+
+impl SignalTrigger {
+    /// Creates a new `SignalTrigger`.
+    ///
+    /// If `leave` is `None`, the `enter` signal toggles the context.
+    pub fn new(enter: c_int, leave: Option<c_int>) -> Self {
+        SignalTrigger { enter, leave }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        match *cfg {
+            Value::String(ref signal) => Ok(Self::new(Self::signal_from_name(signal)?, None)),
+            Value::Mapping(ref mapping) => {
+                let enter = mapping.get(&Value::String("enter".to_owned()))
+                    .and_then(|v| v.as_str())
+                    .ok_or(io::Error::new(io::ErrorKind::InvalidData, "Missing enter signal key."))?;
+                let leave = mapping.get(&Value::String("leave".to_owned()))
+                    .and_then(|v| v.as_str())
+                    .ok_or(io::Error::new(io::ErrorKind::InvalidData, "Missing leave signal key."))?;
+
+                let enter = Self::signal_from_name(enter)?;
+                let leave = Self::signal_from_name(leave)?;
+                if enter == leave {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Enter and leave signals must differ, use a single signal to toggle the context.",
+                    ));
+                }
+
+                Ok(Self::new(enter, Some(leave)))
+            },
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown configuration format")),
+        }
+    }
+
+    /// Parses a signal name like `SIGUSR1` or `usr1`.
+    fn signal_from_name(name: &str) -> io::Result<c_int> {
+        let upper = name.trim().to_uppercase();
+        let short = if upper.starts_with("SIG") { &upper[3..] } else { &upper[..] };
+
+        SIGNALS.iter()
+            .find(|&&(n, _)| n == short)
+            .map(|&(_, signal)| signal)
+            .ok_or(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported signal '{}'.", name),
+            ))
+    }
+}
+
+impl Trigger for SignalTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let h = handle.new_tokio_handle();
+        let enter = Signal::with_handle(self.enter, h)
+            .flatten_stream();
+
+        match self.leave {
+            Some(leave) => {
+                let leave = Signal::with_handle(leave, h)
+                    .flatten_stream()
+                    .map(|_| false);
+
+                Box::new(changes(enter.map(|_| true).select(leave)))
+            },
+            None => {
+                let mut is_active = false;
+                let stream = enter.map(move |_| {
+                    is_active = !is_active;
+                    if is_active { Activity::Active } else { Activity::Inactive }
+                });
+
+                Box::new(stream)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_yaml::Mapping;
+    use tokio_core::reactor::{Core, Timeout};
+
+    use super::*;
+
+    #[test]
+    fn load_cfg() {
+        let trigger = SignalTrigger::from_config(&Value::String("SIGUSR1".to_owned())).unwrap();
+        assert_eq!(trigger.enter, libc::SIGUSR1);
+        assert_eq!(trigger.leave, None);
+
+        let mut map = Mapping::new();
+        map.insert(Value::String("enter".to_owned()), Value::String("usr1".to_owned()));
+        map.insert(Value::String("leave".to_owned()), Value::String("USR2".to_owned()));
+        let trigger = SignalTrigger::from_config(&Value::Mapping(map)).unwrap();
+        assert_eq!(trigger.enter, libc::SIGUSR1);
+        assert_eq!(trigger.leave, Some(libc::SIGUSR2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail_unsupported() {
+        SignalTrigger::from_config(&Value::String("SIGTERM".to_owned())).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail_same_signals() {
+        let mut map = Mapping::new();
+        map.insert(Value::String("enter".to_owned()), Value::String("SIGHUP".to_owned()));
+        map.insert(Value::String("leave".to_owned()), Value::String("SIGHUP".to_owned()));
+        SignalTrigger::from_config(&Value::Mapping(map)).unwrap();
+    }
+
+    #[test]
+    fn toggles_on_signal() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let mut trigger = SignalTrigger::new(libc::SIGUSR2, None);
+        let stream = trigger.listen(handle.clone());
+
+        // The signal handler is installed when the stream is first polled,
+        // so raise the signal slightly delayed.
+        let raise = Timeout::new(Duration::from_millis(50), &handle).unwrap()
+            .map(|_| unsafe { libc::raise(libc::SIGUSR2); })
+            .map_err(|_| ());
+        handle.spawn(raise);
+
+        let (activity, _) = core.run(stream.into_future())
+            .map_err(|(err, _)| err)
+            .unwrap();
+        assert_eq!(activity, Some(Activity::Active));
+    }
+}
@@ -0,0 +1,300 @@
+use std::f64::consts::PI;
+use std::io;
+use std::str::FromStr;
+
+use futures::future;
+use futures::prelude::*;
+use humantime;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use triggers::{Activity, Trigger};
+use triggers::schedule::{DAY, Schedule, ScheduleStream};
+
+pub const TRIGGER_NAME: &'static str = "sun";
+
+/// The julian date of the unix epoch.
+const JULIAN_UNIX_EPOCH: f64 = 2440587.5;
+
+/// The julian date of 2000-01-01 12:00 UTC.
+const JULIAN_J2000: f64 = 2451545.0;
+
+/// An evidence source that is active between two points in the course
+/// of the sun, e.g. from 30 minutes before sunset until sunrise.
+///
+/// The sun's position is computed locally from the configured coordinates,
+/// so no network connection is required.
+#[derive(Debug)]
+pub struct SunTrigger(SunSchedule);
+
+/// The schedule of a `SunTrigger`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SunSchedule {
+    latitude: f64,
+    longitude: f64,
+    enter: SunTime,
+    leave: SunTime,
+}
+
+/// A point in the course of the sun relative to one of the sun's events.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SunTime {
+    event: SunEvent,
+
+    /// The offset to the event in seconds.
+    offset: i64,
+}
+
+/// The events in the course of the sun.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SunEvent {
+    /// The start of the civil twilight in the morning.
+    Dawn,
+    Sunrise,
+    Sunset,
+    /// The end of the civil twilight in the evening.
+    Dusk,
+}
+
+#[derive(Debug, Deserialize)]
+struct SunConfig {
+    latitude: f64,
+    longitude: f64,
+    #[serde(default = "SunConfig::default_enter")]
+    enter: String,
+    #[serde(default = "SunConfig::default_leave")]
+    leave: String,
+}
+
+impl SunTrigger {
+    pub fn new(schedule: SunSchedule) -> Self {
+        SunTrigger(schedule)
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        let cfg: SunConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid sun configuration: {}", e)))?;
+
+        if cfg.latitude.abs() > 90.0 || cfg.longitude.abs() > 180.0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Coordinates are out of range."));
+        }
+
+        Ok(Self::new(SunSchedule {
+            latitude: cfg.latitude,
+            longitude: cfg.longitude,
+            enter: cfg.enter.parse()?,
+            leave: cfg.leave.parse()?,
+        }))
+    }
+}
+
+impl Trigger for SunTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        match ScheduleStream::new(self.0, &handle) {
+            Ok(stream) => Box::new(stream),
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+impl SunSchedule {
+    /// Computes the point in time of the given sun event on the given day
+    /// in seconds since the unix epoch.
+    ///
+    /// The day is given as days since the unix epoch. Returns `None` if the
+    /// event does not occur on that day, as happens close to the poles.
+    ///
+    /// See https://en.wikipedia.org/wiki/Sunrise_equation.
+    fn event_time(&self, event: SunEvent, day: i64) -> Option<i64> {
+        let n = (day as f64 + JULIAN_UNIX_EPOCH - JULIAN_J2000 + 0.0008).ceil();
+        let mean_solar_noon = n - self.longitude / 360.0;
+
+        let anomaly = (357.5291 + 0.98560028 * mean_solar_noon) % 360.0;
+        let center = 1.9148 * sin(anomaly) + 0.02 * sin(2.0 * anomaly) + 0.0003 * sin(3.0 * anomaly);
+        let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372) % 360.0;
+        let transit = JULIAN_J2000 + mean_solar_noon +
+            0.0053 * sin(anomaly) - 0.0069 * sin(2.0 * ecliptic_longitude);
+
+        let declination = (sin(ecliptic_longitude) * sin(23.4397)).asin();
+        let elevation = match event {
+            SunEvent::Sunrise | SunEvent::Sunset => -0.833,
+            SunEvent::Dawn | SunEvent::Dusk => -6.0,
+        };
+        let cos_hour_angle = (sin(elevation) - sin(self.latitude) * declination.sin()) /
+            (cos(self.latitude) * declination.cos());
+        if cos_hour_angle.abs() > 1.0 {
+            return None;
+        }
+
+        let hour_angle = cos_hour_angle.acos() * 180.0 / PI;
+        let julian = match event {
+            SunEvent::Dawn | SunEvent::Sunrise => transit - hour_angle / 360.0,
+            SunEvent::Sunset | SunEvent::Dusk => transit + hour_angle / 360.0,
+        };
+
+        Some(((julian - JULIAN_UNIX_EPOCH) * DAY as f64).round() as i64)
+    }
+
+    /// Computes the points in time of the given sun time on the given days.
+    fn times(&self, time: SunTime, days: &[i64]) -> Vec<i64> {
+        days.iter()
+            .filter_map(|&day| self.event_time(time.event, day))
+            .map(|t| t + time.offset)
+            .collect()
+    }
+}
+
+impl Schedule for SunSchedule {
+    fn intervals(&mut self, from: i64, to: i64) -> io::Result<Vec<(i64, i64)>> {
+        // Look at some days more, so spans crossing the boundaries are found.
+        let days = (from / DAY - 1..to / DAY + 2).collect::<Vec<_>>();
+        let leave_times = self.times(self.leave, &days);
+
+        let intervals = self.times(self.enter, &days)
+            .into_iter()
+            .filter_map(|enter| {
+                leave_times.iter()
+                    .cloned()
+                    .filter(|&leave| leave > enter)
+                    .min()
+                    .map(|leave| (enter, leave))
+            })
+            .filter(|&(enter, leave)| enter < to && leave > from)
+            .collect();
+
+        Ok(intervals)
+    }
+}
+
+impl FromStr for SunTime {
+    type Err = io::Error;
+
+    /// Parses sun times like `sunset`, `sunrise+1h` or `dusk-1h 30m`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, offset) = match s.find(|c| c == '+' || c == '-') {
+            Some(idx) => {
+                let duration = humantime::parse_duration(s[idx + 1..].trim())
+                    .map_err(|e| io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid offset in sun time '{}': {}", s, e),
+                    ))?;
+                let secs = duration.as_secs() as i64;
+
+                (&s[..idx], if s[idx..].starts_with('-') { -secs } else { secs })
+            },
+            None => (s, 0),
+        };
+
+        let event = match name.trim().to_lowercase().as_str() {
+            "dawn" => SunEvent::Dawn,
+            "sunrise" => SunEvent::Sunrise,
+            "sunset" => SunEvent::Sunset,
+            "dusk" => SunEvent::Dusk,
+            _ => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown sun event in '{}'.", s),
+            )),
+        };
+
+        Ok(SunTime { event, offset })
+    }
+}
+
+impl SunConfig {
+    fn default_enter() -> String {
+        "sunset".to_owned()
+    }
+
+    fn default_leave() -> String {
+        "sunrise".to_owned()
+    }
+}
+
+fn sin(degrees: f64) -> f64 {
+    (degrees * PI / 180.0).sin()
+}
+
+fn cos(degrees: f64) -> f64 {
+    (degrees * PI / 180.0).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2018-06-21 in days since the unix epoch.
+    const MIDSUMMER_2018: i64 = 17703;
+
+    fn berlin() -> SunSchedule {
+        SunSchedule {
+            latitude: 52.52,
+            longitude: 13.405,
+            enter: "sunset".parse().unwrap(),
+            leave: "sunrise".parse().unwrap(),
+        }
+    }
+
+    fn assert_close(actual: Option<i64>, expected: i64) {
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() < 3 * 60, "{} is not close to {}", actual, expected);
+    }
+
+    #[test]
+    fn parse_sun_time() {
+        assert_eq!("sunset".parse::<SunTime>().unwrap(), SunTime { event: SunEvent::Sunset, offset: 0 });
+        assert_eq!("sunset-30m".parse::<SunTime>().unwrap(), SunTime { event: SunEvent::Sunset, offset: -1800 });
+        assert_eq!("Dawn + 1h 30m".parse::<SunTime>().unwrap(), SunTime { event: SunEvent::Dawn, offset: 5400 });
+        assert!("noon".parse::<SunTime>().is_err());
+        assert!("sunrise+soon".parse::<SunTime>().is_err());
+    }
+
+    #[test]
+    fn load_cfg() {
+        let cfg = serde_yaml::from_str(r#"
+            latitude: 52.52
+            longitude: 13.405
+            enter: sunset-30m
+        "#).unwrap();
+        let trigger = SunTrigger::from_config(&cfg).unwrap();
+
+        assert_eq!(trigger.0.enter, SunTime { event: SunEvent::Sunset, offset: -1800 });
+        assert_eq!(trigger.0.leave, SunTime { event: SunEvent::Sunrise, offset: 0 });
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        let cfg = serde_yaml::from_str("latitude: 100\nlongitude: 0").unwrap();
+        SunTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    fn event_times() {
+        let sched = berlin();
+
+        assert_close(sched.event_time(SunEvent::Dawn, MIDSUMMER_2018), 1529545980);
+        assert_close(sched.event_time(SunEvent::Sunrise, MIDSUMMER_2018), 1529548980);
+        assert_close(sched.event_time(SunEvent::Sunset, MIDSUMMER_2018), 1529609580);
+        assert_close(sched.event_time(SunEvent::Dusk, MIDSUMMER_2018), 1529612580);
+    }
+
+    #[test]
+    fn polar_day() {
+        let tromso = SunSchedule { latitude: 69.65, longitude: 18.96, ..berlin() };
+
+        assert_eq!(tromso.event_time(SunEvent::Sunset, MIDSUMMER_2018), None);
+    }
+
+    #[test]
+    fn night_intervals() {
+        let mut sched = berlin();
+        let noon = MIDSUMMER_2018 * DAY + DAY / 2;
+
+        let intervals = sched.intervals(noon, noon + DAY).unwrap();
+        assert!(intervals.iter().any(|&(enter, leave)| {
+            (enter - 1529609580).abs() < 3 * 60 && (leave - (1529548980 + DAY)).abs() < 3 * 60
+        }));
+        assert!(intervals.iter().all(|&(enter, leave)| enter < leave));
+    }
+}
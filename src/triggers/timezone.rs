@@ -0,0 +1,239 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, NativeEndian};
+use futures::future;
+use futures::prelude::*;
+use futures::stream;
+use libc;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use context::config_error;
+use multi::Multi;
+use triggers::{changes, inotify, Activity, Trigger};
+
+pub const TRIGGER_NAME: &'static str = "timezone";
+
+/// An evidence source that is active while the system is set to one
+/// of the given timezones, or, using `except`, to any other timezone.
+///
+/// The timezone is read from `TZ` or the target of the `/etc/localtime`
+/// link, which is watched for changes, e.g. by `timedatectl` or automatic
+/// timezone updates while travelling.
+#[derive(Clone, Debug)]
+pub struct TimezoneTrigger {
+    zones: Vec<String>,
+    except: bool,
+    localtime: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimezoneConfig {
+    zone: Option<Multi<String>>,
+    except: Option<Multi<String>>,
+    localtime: Option<String>,
+}
+
+impl TimezoneTrigger {
+    /// Creates a new `TimezoneTrigger`.
+    ///
+    /// If `except` is set, the trigger is active while the system is set
+    /// to none of the given zones.
+    pub fn new(zones: Vec<String>, except: bool) -> Self {
+        TimezoneTrigger {
+            zones,
+            except,
+            localtime: PathBuf::from("/etc/localtime"),
+        }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Ok(zones) = serde_yaml::from_value::<Multi<String>>(cfg.clone()) {
+            return Ok(Self::new(zones.into_iter().collect(), false));
+        }
+
+        let cfg: TimezoneConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| config_error(TRIGGER_NAME, e))?;
+        let mut trigger = match (cfg.zone, cfg.except) {
+            (Some(zones), None) => Self::new(zones.into_iter().collect(), false),
+            (None, Some(zones)) => Self::new(zones.into_iter().collect(), true),
+            _ => return Err(config_error(TRIGGER_NAME, "expected either zone or except")),
+        };
+        if let Some(localtime) = cfg.localtime {
+            trigger.localtime = PathBuf::from(localtime);
+        }
+
+        Ok(trigger)
+    }
+
+    fn is_active(&self) -> io::Result<bool> {
+        let zone = current_zone(&self.localtime)?;
+        let listed = zone.map_or(false, |zone| self.zones.contains(&zone));
+
+        Ok(listed != self.except)
+    }
+
+    fn watch(&self, handle: &Handle) -> io::Result<Box<Stream<Item = (), Error = io::Error>>> {
+        // The link is usually replaced instead of modified, so watch
+        // the directory containing it.
+        let dir = self.localtime.parent().unwrap_or(Path::new("/"));
+        let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_TO | libc::IN_CLOSE_WRITE;
+        let name = self.localtime.file_name().map(|n| n.as_bytes().to_vec());
+
+        let events = inotify::watch(&[(dir, mask)], handle)?
+            .filter(move |batch| name.as_ref().map_or(true, |name| contains_name(batch, name)))
+            .map(|_| ());
+        Ok(Box::new(events))
+    }
+}
+
+impl Trigger for TimezoneTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let trigger = self.clone();
+        let checks = stream::once(Ok(())).and_then(move |_| trigger.is_active());
+
+        // The process environment doesn't change, so if TZ is set there is
+        // nothing to watch.
+        if env::var_os("TZ").is_some() {
+            return Box::new(changes(checks.chain(future::empty().into_stream())));
+        }
+
+        let trigger = self.clone();
+        match self.watch(&handle) {
+            Ok(events) => {
+                let updates = events.and_then(move |_| trigger.is_active());
+                Box::new(changes(checks.chain(updates)))
+            },
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+/// Determines the current timezone, like `Europe/Berlin`.
+///
+/// Returns `None` if the timezone is not known, e.g. because
+/// `/etc/localtime` is a copy instead of a link.
+fn current_zone(localtime: &Path) -> io::Result<Option<String>> {
+    if let Ok(tz) = env::var("TZ") {
+        // `TZ=:Europe/Berlin` is equivalent to `TZ=Europe/Berlin`.
+        let zone = if tz.starts_with(':') { &tz[1..] } else { &tz[..] };
+        return Ok(Some(zone.to_owned()));
+    }
+
+    let target = match fs::read_link(localtime) {
+        Ok(target) => target,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound || e.kind() == io::ErrorKind::InvalidInput => {
+            return Ok(None);
+        },
+        Err(e) => return Err(e),
+    };
+
+    Ok(zone_from_path(&target.to_string_lossy()))
+}
+
+/// Extracts the zone name from a path like `/usr/share/zoneinfo/Europe/Berlin`.
+fn zone_from_path(path: &str) -> Option<String> {
+    path.find("zoneinfo/")
+        .map(|idx| path[idx + "zoneinfo/".len()..].to_owned())
+        .filter(|zone| !zone.is_empty())
+}
+
+/// Checks whether a batch of raw inotify events concerns the given file name.
+fn contains_name(batch: &[u8], name: &[u8]) -> bool {
+    // struct inotify_event { int wd; uint32_t mask, cookie, len; char name[]; }
+    const HEADER_LEN: usize = 16;
+    let mut offset = 0;
+
+    while offset + HEADER_LEN <= batch.len() {
+        let len = NativeEndian::read_u32(&batch[offset + 12..offset + 16]) as usize;
+
+        let start = offset + HEADER_LEN;
+        let end = (start + len).min(batch.len());
+        let event_name = batch[start..end].split(|&b| b == 0).next().unwrap_or(&[]);
+        if event_name == name {
+            return true;
+        }
+
+        offset = start + len;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+    use std::process;
+    use std::time::Duration;
+
+    use tokio_core::reactor::{Core, Timeout};
+
+    use super::*;
+
+    #[test]
+    fn load_cfg() {
+        let trigger = TimezoneTrigger::from_config(&Value::String("Europe/Berlin".to_owned())).unwrap();
+        assert_eq!(trigger.zones, vec!["Europe/Berlin"]);
+        assert!(!trigger.except);
+
+        let cfg = serde_yaml::from_str("{ except: [Europe/Berlin, Europe/Vienna] }").unwrap();
+        let trigger = TimezoneTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.zones.len(), 2);
+        assert!(trigger.except);
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        let cfg = serde_yaml::from_str("{ zone: Europe/Berlin, except: Europe/Vienna }").unwrap();
+        TimezoneTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    fn zone_paths() {
+        assert_eq!(zone_from_path("/usr/share/zoneinfo/Europe/Berlin"), Some("Europe/Berlin".to_owned()));
+        assert_eq!(zone_from_path("../usr/share/zoneinfo/UTC"), Some("UTC".to_owned()));
+        assert_eq!(zone_from_path("/etc/localtime.bak"), None);
+    }
+
+    #[test]
+    fn follows_link() {
+        // Relies on TZ being unset, as it is in the usual test environments.
+        if env::var_os("TZ").is_some() {
+            return;
+        }
+
+        let dir = env::temp_dir().join(format!("runtext-timezone-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let localtime = dir.join("localtime");
+        symlink("/usr/share/zoneinfo/Europe/Berlin", &localtime).unwrap();
+
+        let mut trigger = TimezoneTrigger::new(vec!["Europe/Berlin".to_owned()], true);
+        trigger.localtime = localtime.clone();
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let stream = trigger.listen(handle.clone());
+
+        // Replace the link like timedatectl does.
+        let travel = Timeout::new(Duration::from_millis(50), &handle).unwrap()
+            .map(move |_| {
+                let tmp = localtime.with_extension("new");
+                symlink("/usr/share/zoneinfo/America/New_York", &tmp).unwrap();
+                fs::rename(&tmp, &localtime).unwrap();
+            })
+            .map_err(|_| ());
+        handle.spawn(travel);
+
+        let (activity, _) = core.run(stream.into_future())
+            .map_err(|(err, _)| err)
+            .unwrap();
+        assert_eq!(activity, Some(Activity::Active));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::Duration;
+
+use futures::future;
+use futures::prelude::*;
+use humantime;
+use libc::{self, c_char};
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use context::config_error;
+use multi::Multi;
+use triggers::{Activity, Trigger};
+use triggers::poll::PollStream;
+
+pub const TRIGGER_NAME: &'static str = "users";
+
+/// An evidence source that is active while certain users are logged in,
+/// or while anyone is logged in remotely via SSH.
+///
+/// Sessions are read from the utmp database, which is maintained by
+/// `login`, `sshd` and display managers alike.
+#[derive(Clone, Debug)]
+pub struct UsersTrigger {
+    users: Vec<String>,
+    remote: bool,
+    interval: Duration,
+    utmp: PathBuf,
+}
+
+/// A login session.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Session {
+    pub user: String,
+    pub line: String,
+
+    /// The remote host for SSH sessions, or the X display for local
+    /// graphical sessions.
+    pub host: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsersConfig {
+    user: Option<Multi<String>>,
+    #[serde(default)]
+    remote: bool,
+    interval: Option<String>,
+    utmp: Option<String>,
+}
+
+impl UsersTrigger {
+    /// Creates a new `UsersTrigger`.
+    ///
+    /// If `users` is empty, sessions of any user count. If `remote` is set,
+    /// only remote sessions count.
+    pub fn new(users: Vec<String>, remote: bool) -> Self {
+        UsersTrigger {
+            users,
+            remote,
+            interval: Duration::from_secs(5),
+            utmp: PathBuf::from("/var/run/utmp"),
+        }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Value::String(ref user) = *cfg {
+            return Ok(Self::new(vec![user.clone()], false));
+        }
+
+        let cfg: UsersConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| config_error(TRIGGER_NAME, e))?;
+        let users = cfg.user.map(|u| u.into_iter().collect()).unwrap_or_else(Vec::new);
+        if users.is_empty() && !cfg.remote {
+            return Err(config_error(TRIGGER_NAME, "missing user names or remote condition"));
+        }
+
+        let mut trigger = Self::new(users, cfg.remote);
+        if let Some(interval) = cfg.interval {
+            trigger.interval = humantime::parse_duration(&interval).map_err(|e| config_error(TRIGGER_NAME, e))?;
+        }
+        if let Some(utmp) = cfg.utmp {
+            trigger.utmp = PathBuf::from(utmp);
+        }
+
+        Ok(trigger)
+    }
+
+    fn matches(&self, sessions: &[Session]) -> bool {
+        sessions.iter().any(|session| {
+            (self.users.is_empty() || self.users.contains(&session.user)) &&
+                (!self.remote || session.is_remote())
+        })
+    }
+}
+
+impl Trigger for UsersTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let trigger = self.clone();
+        let check = move || {
+            // A broken utmp database must not stop the context from being
+            // driven, it counts as nobody being logged in.
+            let sessions = read_sessions(&trigger.utmp).unwrap_or_else(|err| {
+                eprintln!("Could not read the sessions from '{}': {}.", trigger.utmp.display(), err);
+                Vec::new()
+            });
+
+            Ok(trigger.matches(&sessions))
+        };
+
+        match PollStream::new(self.interval, &handle, check) {
+            Ok(stream) => Box::new(stream),
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+impl Session {
+    /// Checks whether this is a remote session.
+    ///
+    /// Local graphical sessions record their display, e.g. `:0`, as host.
+    pub fn is_remote(&self) -> bool {
+        !self.host.is_empty() && !self.host.starts_with(':')
+    }
+}
+
+/// Reads the active login sessions from a utmp database.
+///
+/// A missing database, as in containers and minimal systems, has no
+/// sessions.
+fn read_sessions(path: &Path) -> io::Result<Vec<Session>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let record_len = mem::size_of::<libc::utmpx>();
+
+    let sessions = data.chunks(record_len)
+        .filter(|chunk| chunk.len() == record_len)
+        .map(|chunk| unsafe { ptr::read_unaligned(chunk.as_ptr() as *const libc::utmpx) })
+        .filter(|record| record.ut_type == libc::USER_PROCESS)
+        .map(|record| Session {
+            user: c_string(&record.ut_user),
+            line: c_string(&record.ut_line),
+            host: c_string(&record.ut_host),
+        })
+        .collect();
+
+    Ok(sessions)
+}
+
+/// Converts a fixed-size, not necessarily NUL-terminated field of
+/// a utmp record into a string.
+fn c_string(field: &[c_char]) -> String {
+    let bytes = field.iter()
+        .map(|&c| c as u8)
+        .take_while(|&b| b != 0)
+        .collect::<Vec<_>>();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::process;
+    use std::slice;
+
+    use super::*;
+
+    fn record(kind: i16, user: &str, line: &str, host: &str) -> Vec<u8> {
+        let mut record: libc::utmpx = unsafe { mem::zeroed() };
+        record.ut_type = kind;
+        for (dst, src) in record.ut_user.iter_mut().zip(user.bytes()) {
+            *dst = src as c_char;
+        }
+        for (dst, src) in record.ut_line.iter_mut().zip(line.bytes()) {
+            *dst = src as c_char;
+        }
+        for (dst, src) in record.ut_host.iter_mut().zip(host.bytes()) {
+            *dst = src as c_char;
+        }
+
+        let bytes = unsafe {
+            slice::from_raw_parts(&record as *const libc::utmpx as *const u8, mem::size_of::<libc::utmpx>())
+        };
+        bytes.to_vec()
+    }
+
+    #[test]
+    fn load_cfg() {
+        let trigger = UsersTrigger::from_config(&Value::String("alice".to_owned())).unwrap();
+        assert_eq!(trigger.users, vec!["alice"]);
+        assert!(!trigger.remote);
+
+        let cfg = serde_yaml::from_str("{ remote: true }").unwrap();
+        let trigger = UsersTrigger::from_config(&cfg).unwrap();
+        assert!(trigger.users.is_empty());
+        assert!(trigger.remote);
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        let cfg = serde_yaml::from_str("{ remote: false }").unwrap();
+        UsersTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    fn sessions() {
+        let path = env::temp_dir().join(format!("runtext-utmp-{}", process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&record(libc::BOOT_TIME, "reboot", "~", "")).unwrap();
+        file.write_all(&record(libc::USER_PROCESS, "alice", "tty2", ":0")).unwrap();
+        file.write_all(&record(libc::USER_PROCESS, "bob", "pts/3", "10.0.0.12")).unwrap();
+        file.write_all(&record(libc::DEAD_PROCESS, "carol", "pts/4", "10.0.0.13")).unwrap();
+        drop(file);
+
+        let sessions = read_sessions(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[1], Session {
+            user: "bob".to_owned(),
+            line: "pts/3".to_owned(),
+            host: "10.0.0.12".to_owned(),
+        });
+
+        assert!(UsersTrigger::new(vec!["alice".to_owned()], false).matches(&sessions));
+        assert!(!UsersTrigger::new(vec!["alice".to_owned()], true).matches(&sessions));
+        assert!(UsersTrigger::new(vec![], true).matches(&sessions));
+        assert!(!UsersTrigger::new(vec!["carol".to_owned()], false).matches(&sessions));
+
+        assert_eq!(read_sessions(&path).unwrap(), vec![]);
+    }
+}
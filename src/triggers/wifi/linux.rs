@@ -0,0 +1,150 @@
+use std::io;
+use std::process::{Command, Stdio};
+
+use futures::future;
+use futures::prelude::*;
+use tokio_core::reactor::Handle;
+use tokio_process::CommandExt;
+
+use super::{Band, WifiInfo};
+
+/// Queries the joined wifi network from `iw`.
+///
+/// Resolves to `None` if no wireless interface is connected.
+pub fn current_network(handle: &Handle) -> Box<Future<Item = Option<WifiInfo>, Error = io::Error>> {
+    let h = handle.clone();
+    let info = iw(&["dev"], handle)
+        .and_then(move |dev| {
+            let links = parse_interfaces(&dev).into_iter()
+                .map(|interface| iw(&["dev", &interface, "link"], &h))
+                .collect::<Vec<_>>();
+
+            future::join_all(links)
+        })
+        .map(|links| links.iter().filter_map(|link| parse_link(link)).next());
+
+    Box::new(info)
+}
+
+fn iw(args: &[&str], handle: &Handle) -> Box<Future<Item = String, Error = io::Error>> {
+    let output = Command::new("iw")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output_async_with_handle(handle.new_tokio_handle())
+        .and_then(|output| {
+            if !output.status.success() {
+                return Err(io::Error::new(io::ErrorKind::Other, "iw failed to query the wireless interfaces."));
+            }
+
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        });
+
+    Box::new(output)
+}
+
+/// Parses the interface names out of the output of `iw dev`.
+fn parse_interfaces(output: &str) -> Vec<String> {
+    output.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.starts_with("Interface ") {
+                Some(line["Interface ".len()..].trim().to_owned())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parses the output of `iw dev <interface> link`.
+fn parse_link(output: &str) -> Option<WifiInfo> {
+    let mut ssid = None;
+    let mut rssi = None;
+    let mut freq = None;
+
+    for line in output.lines() {
+        let mut line_parts = line.trim().splitn(2, ": ");
+        let (key, value) = match (line_parts.next(), line_parts.next()) {
+            (Some(key), Some(value)) => (key, value.trim()),
+            _ => continue,
+        };
+
+        match key {
+            "SSID" => ssid = Some(value.to_owned()),
+            // E.g. `-54 dBm`.
+            "signal" => rssi = value.split_whitespace().next().and_then(|s| s.parse().ok()),
+            // Newer versions print fractional frequencies like `5180.0`.
+            "freq" => freq = value.parse::<f64>().ok().map(|f| f as u32),
+            _ => {},
+        }
+    }
+
+    ssid.map(|ssid| WifiInfo {
+        ssid,
+        rssi,
+        channel: freq.and_then(channel_from_frequency),
+        band: freq.and_then(Band::from_frequency),
+    })
+}
+
+/// Converts a center frequency in MHz into the channel number.
+fn channel_from_frequency(freq: u32) -> Option<u32> {
+    match Band::from_frequency(freq)? {
+        Band::Ghz2_4 if freq == 2484 => Some(14),
+        Band::Ghz2_4 => freq.checked_sub(2407).map(|d| d / 5),
+        Band::Ghz5 => Some((freq - 5000) / 5),
+        // Channel 2 is the only one below the 6 GHz channel raster.
+        Band::Ghz6 if freq == 5935 => Some(2),
+        Band::Ghz6 => freq.checked_sub(5950).map(|d| d / 5),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_iw_output() {
+        let dev = "phy#0
+\tInterface wlp2s0
+\t\tifindex 3
+\t\ttype managed
+";
+        assert_eq!(parse_interfaces(dev), vec!["wlp2s0"]);
+
+        let link = "Connected to 8c:3b:ad:12:34:56 (on wlp2s0)
+\tSSID: R56
+\tfreq: 5180.0
+\tRX: 117683 bytes (705 packets)
+\tsignal: -54 dBm
+";
+        assert_eq!(parse_link(link), Some(WifiInfo {
+            ssid: "R56".to_owned(),
+            rssi: Some(-54),
+            channel: Some(36),
+            band: Some(Band::Ghz5),
+        }));
+
+        assert_eq!(parse_link("Not connected.\n"), None);
+    }
+
+    #[test]
+    fn channels() {
+        assert_eq!(channel_from_frequency(2412), Some(1));
+        assert_eq!(channel_from_frequency(2484), Some(14));
+        assert_eq!(channel_from_frequency(5745), Some(149));
+        assert_eq!(channel_from_frequency(5975), Some(5));
+    }
+
+    #[test]
+    fn channels_at_band_edges() {
+        assert_eq!(channel_from_frequency(2400), None);
+        assert_eq!(channel_from_frequency(2406), None);
+        assert_eq!(channel_from_frequency(5930), None);
+        assert_eq!(channel_from_frequency(5935), Some(2));
+        assert_eq!(channel_from_frequency(5949), None);
+        assert_eq!(channel_from_frequency(5955), Some(1));
+        assert_eq!(channel_from_frequency(7115), Some(233));
+    }
+}
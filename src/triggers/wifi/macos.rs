@@ -1,129 +1,93 @@
 use std::io;
 use std::process::Command;
 use std::str;
-use std::time::{Duration, Instant};
 
 use futures::prelude::*;
-use serde_yaml::Value;
-use tokio_core::reactor::{Handle, Timeout};
-
-use triggers::{Activity, Trigger};
-
-const AIRPORT_UTIL_PATH: &'static str = "/System/Library/PrivateFrameworks/Apple80211.framework/Versions/Current/Resources/airport";
-
-/// A wifi evidence source that signals when a specific wifi network
-/// is joined or left.
-#[derive(Debug)]
-pub struct WifiTrigger(String);
-
-impl WifiTrigger {
-    pub fn new<N: Into<String>>(wifi_name: N) -> Self {
-        WifiTrigger(wifi_name.into())
-    }
-
-    pub fn from_config(cfg: &Value) -> io::Result<Self> {
-        match *cfg {
-            Value::String(ref ssid) => Ok(Self::new(ssid.as_ref())),
-            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown configuration format")),
-        }
-    }
+use tokio_core::reactor::Handle;
+use tokio_process::CommandExt;
+
+use super::{AIRPORT_UTIL_PATH, Band, WifiInfo};
+
+/// Queries the joined wifi network from the airport utility.
+///
+/// Resolves to `None` if wifi is turned off.
+pub fn current_network(handle: &Handle) -> Box<Future<Item = Option<WifiInfo>, Error = io::Error>> {
+    let info = Command::new(AIRPORT_UTIL_PATH)
+        .arg("-I")
+        .output_async_with_handle(handle.new_tokio_handle())
+        .and_then(|output| {
+            let output = str::from_utf8(&output.stdout)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Got non-UTF-8 output from airport utility"))?;
+
+            parse_airport_info(output)
+        });
+
+    Box::new(info)
 }
 
-impl Trigger for WifiTrigger {
-    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
-        Box::new(WifiStream::new(self.0.clone(), handle))
-    }
-}
-
-#[derive(Debug)]
-struct WifiStream {
-    name: String,
-    timeout: Timeout,
-    was_same: bool,
-}
-
-impl WifiStream {
-    pub fn new(name: String, handle: Handle) -> Self {
-        WifiStream {
-            name,
-            timeout: Timeout::new(Duration::from_millis(0), &handle).unwrap(),
-            was_same: false,
+/// Parses the output of `airport -I`.
+fn parse_airport_info(output: &str) -> io::Result<Option<WifiInfo>> {
+    let mut ssid = None;
+    let mut rssi = None;
+    let mut channel = None;
+
+    for line in output.lines() {
+        let mut line_parts = line.trim().splitn(2, ": ");
+        let (key, value) = match (line_parts.next(), line_parts.next()) {
+            (Some(key), Some(value)) => (key, value.trim()),
+            _ => continue,
+        };
+
+        match key {
+            // The line "AirPort: Off" signals that wifi is turned off.
+            "AirPort" => return Ok(None),
+            "SSID" => ssid = Some(value.to_owned()),
+            "agrCtlRSSI" => rssi = value.parse().ok(),
+            // The channel is followed by the channel width, e.g. `149,80`.
+            "channel" => channel = value.split(',').next().and_then(|c| c.parse().ok()),
+            _ => {},
         }
     }
 
-    /// Parse wifi SSID out of airport utility's output.
-    ///
-    /// Returns `None` if wifi is turned off and the SSID otherwise.
-    fn get_wifi_name() -> io::Result<Option<String>> {
-        let output = Command::new(AIRPORT_UTIL_PATH)
-            .arg("-I")
-            .output()?;
-
-        let mut line_parts = str::from_utf8(&output.stdout)
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Got non-UTF-8 output from airport utility"))?
-            .lines()
-            .map(|l| l.trim())
-            .filter(|l| l.starts_with("SSID: ") || l.starts_with("AirPort: "))
-            .nth(0)
-            .ok_or(io::Error::new(io::ErrorKind::InvalidData, "Missing SSID or AirPort line"))?
-            .splitn(2, ": ");
-
-        // If this is "AirPort", than we have the line "AirPort: Off", which
-        // signals that wifi is turned off.
-        match line_parts.next() {
-            Some("AirPort") | None => return Ok(None),
-            _ => {}
-        }
-
-        Ok(line_parts.next().map(Into::into))
-    }
-}
-
-impl Stream for WifiStream {
-    type Item = Activity;
-    type Error = io::Error;
-
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        try_ready!(self.timeout.poll());
-        self.timeout.reset(Instant::now() + Duration::from_millis(5000));
-
-        let new_ssid = Self::get_wifi_name().ok().and_then(|v| v);
-        match new_ssid {
-            Some(ssid) => {
-                let is_same = ssid == self.name;
-
-                if is_same && !self.was_same {
-                    self.was_same = true;
-                    Ok(Async::Ready(Some(Activity::Active)))
-                } else if !is_same && self.was_same {
-                    self.was_same = false;
-                    Ok(Async::Ready(Some(Activity::Inactive)))
-                } else {
-                    try_ready!(self.timeout.poll());
-
-                    Ok(Async::NotReady)
-                }
-            },
-            None => {
-                if self.was_same {
-                    self.was_same = false;
-                    Ok(Async::Ready(Some(Activity::Inactive)))
-                } else {
-                    try_ready!(self.timeout.poll());
-
-                    Ok(Async::NotReady)
-                }
-            },
-        }
-    }
+    let ssid = ssid.ok_or(io::Error::new(io::ErrorKind::InvalidData, "Missing SSID or AirPort line"))?;
+    Ok(Some(WifiInfo {
+        ssid,
+        rssi,
+        channel,
+        band: channel.and_then(Band::from_channel),
+    }))
 }
 
 #[cfg(test)]
 mod tests {
+    use tokio_core::reactor::Core;
+
     use super::*;
 
     #[test]
     fn wifi_name() {
-        WifiStream::get_wifi_name().unwrap();
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        core.run(current_network(&handle)).unwrap();
+    }
+
+    #[test]
+    fn parse_info() {
+        let output = "     agrCtlRSSI: -55
+     agrExtRSSI: 0
+    agrCtlNoise: -90
+          state: running
+          BSSID: 8c:3b:ad:12:34:56
+           SSID: R56
+        channel: 149,80
+";
+        assert_eq!(parse_airport_info(output).unwrap(), Some(WifiInfo {
+            ssid: "R56".to_owned(),
+            rssi: Some(-55),
+            channel: Some(149),
+            band: Some(Band::Ghz5),
+        }));
+
+        assert_eq!(parse_airport_info("AirPort: Off\n").unwrap(), None);
     }
 }
@@ -1,6 +1,255 @@
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::future;
+use futures::prelude::*;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use triggers::{Activity, Trigger};
+use triggers::poll::PollStream;
+
 #[cfg_attr(target_os = "macos", path = "macos.rs")]
+#[cfg_attr(target_os = "linux", path = "linux.rs")]
 mod imp;
 
+pub use self::imp::*;
+
 pub const TRIGGER_NAME: &'static str = "wifi";
 
-pub use self::imp::*;
+/// The location of the airport utility on macOS.
+pub const AIRPORT_UTIL_PATH: &'static str = "/System/Library/PrivateFrameworks/Apple80211.framework/Versions/Current/Resources/airport";
+
+/// How often the current network is checked, in seconds.
+const INTERVAL: u64 = 5;
+
+/// The default hysteresis for the minimum signal strength in dB.
+const DEFAULT_HYSTERESIS: i32 = 5;
+
+/// A wifi evidence source that signals when a specific wifi network
+/// is joined or left.
+///
+/// Optionally the signal has to be strong enough, or the access point
+/// has to use a certain band or channel, which tells apart rooms that
+/// share an SSID.
+#[derive(Clone, Debug)]
+pub struct WifiTrigger {
+    ssid: String,
+    min_rssi: Option<i32>,
+    hysteresis: i32,
+    band: Option<Band>,
+    channel: Option<u32>,
+}
+
+/// The properties of the joined wifi network.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WifiInfo {
+    pub ssid: String,
+
+    /// The signal strength in dBm.
+    pub rssi: Option<i32>,
+    pub channel: Option<u32>,
+    pub band: Option<Band>,
+}
+
+/// The frequency bands wifi networks operate in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+pub enum Band {
+    #[serde(rename = "2.4ghz")]
+    Ghz2_4,
+    #[serde(rename = "5ghz")]
+    Ghz5,
+    #[serde(rename = "6ghz")]
+    Ghz6,
+}
+
+#[derive(Debug, Deserialize)]
+struct WifiConfig {
+    ssid: String,
+    min_rssi: Option<i32>,
+    hysteresis: Option<i32>,
+    band: Option<Band>,
+    channel: Option<u32>,
+}
+
+impl WifiTrigger {
+    pub fn new<N: Into<String>>(wifi_name: N) -> Self {
+        WifiTrigger {
+            ssid: wifi_name.into(),
+            min_rssi: None,
+            hysteresis: DEFAULT_HYSTERESIS,
+            band: None,
+            channel: None,
+        }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Value::String(ref ssid) = *cfg {
+            return Ok(Self::new(ssid.as_str()));
+        }
+
+        let cfg: WifiConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid wifi configuration: {}", e)))?;
+        let hysteresis = cfg.hysteresis.unwrap_or(DEFAULT_HYSTERESIS);
+        if hysteresis < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "The RSSI hysteresis must not be negative."));
+        }
+
+        Ok(WifiTrigger {
+            ssid: cfg.ssid,
+            min_rssi: cfg.min_rssi,
+            hysteresis,
+            band: cfg.band,
+            channel: cfg.channel,
+        })
+    }
+
+    /// Checks whether the joined network satisfies all conditions.
+    ///
+    /// Once active, the signal may drop `hysteresis` dB below the minimum
+    /// before the context is left, so that it doesn't flap at the edge
+    /// of a room.
+    fn matches(&self, info: &WifiInfo, was_active: bool) -> bool {
+        if info.ssid != self.ssid {
+            return false;
+        }
+
+        let rssi_ok = match (self.min_rssi, info.rssi) {
+            (Some(min), Some(rssi)) if was_active => rssi >= min - self.hysteresis,
+            (Some(min), Some(rssi)) => rssi >= min,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+
+        rssi_ok &&
+            self.band.map_or(true, |band| info.band == Some(band)) &&
+            self.channel.map_or(true, |channel| info.channel == Some(channel))
+    }
+}
+
+impl Trigger for WifiTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let trigger = self.clone();
+        let was_active = Rc::new(Cell::new(false));
+        let h = handle.clone();
+        let check = move || {
+            let trigger = trigger.clone();
+            let was_active = was_active.clone();
+
+            current_network(&h).then(move |info| {
+                // Failing to query the network, e.g. because wifi is turned
+                // off entirely, counts as not being connected.
+                let is_active = info.unwrap_or(None)
+                    .map_or(false, |info| trigger.matches(&info, was_active.get()));
+                was_active.set(is_active);
+
+                Ok(is_active)
+            })
+        };
+
+        match PollStream::new(Duration::from_secs(INTERVAL), &handle, check) {
+            Ok(stream) => Box::new(stream),
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+impl Band {
+    /// Determines the band from a channel number.
+    ///
+    /// This is ambiguous for 6 GHz channels, which reuse the numbers of
+    /// the other bands, prefer `from_frequency` where possible.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn from_channel(channel: u32) -> Option<Band> {
+        if channel >= 1 && channel <= 14 {
+            Some(Band::Ghz2_4)
+        } else if channel >= 32 && channel <= 177 {
+            Some(Band::Ghz5)
+        } else {
+            None
+        }
+    }
+
+    /// Determines the band from a center frequency in MHz.
+    pub fn from_frequency(freq: u32) -> Option<Band> {
+        if freq >= 2400 && freq <= 2500 {
+            Some(Band::Ghz2_4)
+        } else if freq >= 5150 && freq <= 5925 {
+            Some(Band::Ghz5)
+        } else if freq > 5925 && freq <= 7125 {
+            Some(Band::Ghz6)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(ssid: &str, rssi: i32, channel: u32) -> WifiInfo {
+        WifiInfo {
+            ssid: ssid.to_owned(),
+            rssi: Some(rssi),
+            channel: Some(channel),
+            band: Band::from_channel(channel),
+        }
+    }
+
+    #[test]
+    fn load_cfg() {
+        let trigger = WifiTrigger::from_config(&Value::String("R56".to_owned())).unwrap();
+        assert_eq!(trigger.ssid, "R56");
+        assert_eq!(trigger.min_rssi, None);
+
+        let cfg = serde_yaml::from_str("{ ssid: Office, min_rssi: -60, band: 5ghz, channel: 36 }").unwrap();
+        let trigger = WifiTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.min_rssi, Some(-60));
+        assert_eq!(trigger.hysteresis, DEFAULT_HYSTERESIS);
+        assert_eq!(trigger.band, Some(Band::Ghz5));
+        assert_eq!(trigger.channel, Some(36));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        let cfg = serde_yaml::from_str("{ ssid: Office, band: 4ghz }").unwrap();
+        WifiTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    fn rssi_hysteresis() {
+        let mut trigger = WifiTrigger::new("Office");
+        trigger.min_rssi = Some(-60);
+
+        assert!(!trigger.matches(&info("Office", -62, 36), false));
+        assert!(trigger.matches(&info("Office", -58, 36), false));
+        assert!(trigger.matches(&info("Office", -62, 36), true));
+        assert!(!trigger.matches(&info("Office", -66, 36), true));
+        assert!(!trigger.matches(&info("Guests", -40, 36), true));
+    }
+
+    #[test]
+    fn band_and_channel() {
+        let mut trigger = WifiTrigger::new("Office");
+        trigger.band = Some(Band::Ghz5);
+
+        assert!(trigger.matches(&info("Office", -50, 149), false));
+        assert!(!trigger.matches(&info("Office", -50, 6), false));
+
+        trigger.channel = Some(36);
+        assert!(!trigger.matches(&info("Office", -50, 149), false));
+        assert!(trigger.matches(&info("Office", -50, 36), false));
+    }
+
+    #[test]
+    fn bands() {
+        assert_eq!(Band::from_frequency(2437), Some(Band::Ghz2_4));
+        assert_eq!(Band::from_frequency(5180), Some(Band::Ghz5));
+        assert_eq!(Band::from_frequency(5975), Some(Band::Ghz6));
+        assert_eq!(Band::from_channel(165), Some(Band::Ghz5));
+    }
+}
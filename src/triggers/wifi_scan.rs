@@ -0,0 +1,365 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use futures::future;
+use futures::prelude::*;
+use humantime;
+use regex::Regex;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+use tokio_process::CommandExt;
+
+use context::config_error;
+use multi::Multi;
+use triggers::{Activity, Trigger};
+use triggers::poll::PollStream;
+use triggers::wifi::AIRPORT_UTIL_PATH;
+
+pub const TRIGGER_NAME: &'static str = "wifi_scan";
+
+/// An evidence source that is active while a wifi network is in range,
+/// whether or not it has been joined.
+///
+/// This detects locations where the machine is connected via ethernet
+/// but the office wifi is still visible.
+#[derive(Clone, Debug)]
+pub struct WifiScanTrigger {
+    backend: Backend,
+    interval: Duration,
+    ssids: Vec<String>,
+    bssids: Vec<String>,
+}
+
+/// The tools that can list the nearby networks.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Backend {
+    /// `airport -s` on macOS, using the given binary.
+    Airport(PathBuf),
+
+    /// `iw dev <interface> scan` on Linux, which requires root privileges.
+    Iw {
+        command: PathBuf,
+        interface: String,
+    },
+
+    /// `nmcli dev wifi list` on Linux systems running NetworkManager.
+    Nmcli(PathBuf),
+}
+
+/// A network found while scanning.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Network {
+    pub ssid: String,
+
+    /// The MAC address of the access point in lowercase.
+    pub bssid: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WifiScanConfig {
+    ssid: Option<Multi<String>>,
+    bssid: Option<Multi<String>>,
+    backend: Option<BackendName>,
+    command: Option<String>,
+    interface: Option<String>,
+    interval: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BackendName {
+    Airport,
+    Iw,
+    Nmcli,
+}
+
+impl WifiScanTrigger {
+    pub fn new(backend: Backend, ssids: Vec<String>, bssids: Vec<String>) -> Self {
+        WifiScanTrigger {
+            backend,
+            interval: Duration::from_secs(30),
+            ssids,
+            bssids: bssids.into_iter().map(|b| b.to_lowercase()).collect(),
+        }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Value::String(ref ssid) = *cfg {
+            return Ok(Self::new(Self::default_backend(), vec![ssid.clone()], vec![]));
+        }
+
+        let cfg: WifiScanConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| config_error(TRIGGER_NAME, e))?;
+        let ssids = cfg.ssid.map(|s| s.into_iter().collect()).unwrap_or_else(Vec::new);
+        let bssids = cfg.bssid.map(|b| b.into_iter().collect()).unwrap_or_else(Vec::new);
+        if ssids.is_empty() && bssids.is_empty() {
+            return Err(config_error(TRIGGER_NAME, "missing SSID or BSSID"));
+        }
+
+        let custom_command = cfg.command;
+        let command = |default: &str| PathBuf::from(custom_command.clone().unwrap_or(default.to_owned()));
+        let backend = match cfg.backend {
+            Some(BackendName::Airport) => Backend::Airport(command(AIRPORT_UTIL_PATH)),
+            Some(BackendName::Iw) => Backend::Iw {
+                command: command("iw"),
+                interface: cfg.interface.ok_or(config_error(TRIGGER_NAME, "the iw backend requires an interface"))?,
+            },
+            Some(BackendName::Nmcli) => Backend::Nmcli(command("nmcli")),
+            None => match Self::default_backend() {
+                Backend::Airport(_) => Backend::Airport(command(AIRPORT_UTIL_PATH)),
+                _ => Backend::Nmcli(command("nmcli")),
+            },
+        };
+
+        let mut trigger = Self::new(backend, ssids, bssids);
+        if let Some(interval) = cfg.interval {
+            trigger.interval = humantime::parse_duration(&interval).map_err(|e| config_error(TRIGGER_NAME, e))?;
+        }
+
+        Ok(trigger)
+    }
+
+    fn default_backend() -> Backend {
+        if cfg!(target_os = "macos") {
+            Backend::Airport(PathBuf::from(AIRPORT_UTIL_PATH))
+        } else {
+            Backend::Nmcli(PathBuf::from("nmcli"))
+        }
+    }
+
+    /// Checks whether a network with one of the SSIDs and BSSIDs is visible.
+    fn matches(&self, networks: &[Network]) -> bool {
+        networks.iter().any(|network| {
+            (self.ssids.is_empty() || self.ssids.contains(&network.ssid)) &&
+                (self.bssids.is_empty() || self.bssids.contains(&network.bssid))
+        })
+    }
+}
+
+impl Trigger for WifiScanTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let trigger = self.clone();
+        let h = handle.clone();
+        let check = move || {
+            let trigger = trigger.clone();
+
+            // Scans regularly fail while the interface is busy, so don't
+            // end the context because of that.
+            trigger.backend.scan(&h).then(move |res| {
+                let networks = res.unwrap_or_else(|err| {
+                    eprintln!("Failed to scan for wifi networks: {}.", err);
+                    Vec::new()
+                });
+
+                Ok(trigger.matches(&networks))
+            })
+        };
+
+        match PollStream::new(self.interval, &handle, check) {
+            Ok(stream) => Box::new(stream),
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+impl Backend {
+    /// Lists the nearby networks.
+    ///
+    /// Scans take a few seconds, so the scanner runs in the background.
+    pub fn scan(&self, handle: &Handle) -> Box<Future<Item = Vec<Network>, Error = io::Error>> {
+        match *self {
+            Backend::Airport(ref cmd) => Box::new(run(Command::new(cmd).arg("-s"), handle).map(|out| parse_airport(&out))),
+            Backend::Iw { ref command, ref interface } => {
+                Box::new(run(Command::new(command).args(&["dev", interface, "scan"]), handle).map(|out| parse_iw(&out)))
+            },
+            Backend::Nmcli(ref cmd) => {
+                let args = ["-t", "-f", "SSID,BSSID", "dev", "wifi", "list"];
+                Box::new(run(Command::new(cmd).args(&args), handle).map(|out| parse_nmcli(&out)))
+            },
+        }
+    }
+}
+
+fn run(cmd: &mut Command, handle: &Handle) -> Box<Future<Item = String, Error = io::Error>> {
+    let fut = cmd.stdin(Stdio::null())
+        .output_async_with_handle(handle.new_tokio_handle())
+        .and_then(|output| {
+            if !output.status.success() {
+                return Err(io::Error::new(io::ErrorKind::Other, format!("Scanning failed with {}", output.status)));
+            }
+
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        });
+
+    Box::new(fut)
+}
+
+/// Parses the output of `airport -s`.
+///
+/// The SSIDs are right-aligned and may contain spaces, so they are
+/// everything in front of the BSSID.
+fn parse_airport(output: &str) -> Vec<Network> {
+    let bssid = Regex::new("(?i)(?:[0-9a-f]{2}:){5}[0-9a-f]{2}").unwrap();
+
+    output.lines()
+        .filter_map(|line| {
+            let found = bssid.find(line)?;
+            Some(Network {
+                ssid: line[..found.start()].trim().to_owned(),
+                bssid: found.as_str().to_lowercase(),
+            })
+        })
+        .collect()
+}
+
+/// Parses the output of `iw dev <interface> scan`.
+fn parse_iw(output: &str) -> Vec<Network> {
+    let mut networks = Vec::new();
+
+    for line in output.lines() {
+        if line.starts_with("BSS ") {
+            // E.g. `BSS 8c:3b:ad:12:34:56(on wlp2s0) -- associated`.
+            let bssid = line[4..].split(|c| c == '(' || c == ' ').next().unwrap_or("");
+            networks.push(Network {
+                ssid: String::new(),
+                bssid: bssid.to_lowercase(),
+            });
+        } else if line.trim().starts_with("SSID: ") {
+            if let Some(network) = networks.last_mut() {
+                network.ssid = line.trim()["SSID: ".len()..].to_owned();
+            }
+        }
+    }
+
+    networks
+}
+
+/// Parses the terse output of `nmcli -t -f SSID,BSSID dev wifi list`.
+///
+/// Colons within the fields are escaped with backslashes.
+fn parse_nmcli(output: &str) -> Vec<Network> {
+    output.lines()
+        .filter_map(|line| {
+            let mut fields = vec![String::new()];
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => fields.last_mut().unwrap().extend(chars.next()),
+                    ':' => fields.push(String::new()),
+                    c => fields.last_mut().unwrap().push(c),
+                }
+            }
+
+            if fields.len() != 2 {
+                return None;
+            }
+
+            let bssid = fields.pop().unwrap().to_lowercase();
+            let ssid = fields.pop().unwrap();
+            Some(Network { ssid, bssid })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(ssid: &str, bssid: &str) -> Network {
+        Network {
+            ssid: ssid.to_owned(),
+            bssid: bssid.to_owned(),
+        }
+    }
+
+    #[test]
+    fn load_cfg() {
+        let trigger = WifiScanTrigger::from_config(&Value::String("R56".to_owned())).unwrap();
+        assert_eq!(trigger.ssids, vec!["R56"]);
+
+        let cfg = serde_yaml::from_str("{ bssid: ['8C:3B:AD:12:34:56'], backend: iw, interface: wlan0 }").unwrap();
+        let trigger = WifiScanTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.bssids, vec!["8c:3b:ad:12:34:56"]);
+        assert_eq!(trigger.backend, Backend::Iw {
+            command: PathBuf::from("iw"),
+            interface: "wlan0".to_owned(),
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail() {
+        let cfg = serde_yaml::from_str("{ backend: nmcli }").unwrap();
+        WifiScanTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    fn parse_airport_output() {
+        let output = "                            SSID BSSID             RSSI CHANNEL HT CC SECURITY (auth/unicast/group)
+                             R56 8c:3b:ad:12:34:56 -55  149,+1  Y  DE WPA2(PSK/AES/AES)
+                      Guest Wifi 00:11:22:33:44:55 -80  6       Y  -- NONE
+";
+        assert_eq!(parse_airport(output), vec![
+            network("R56", "8c:3b:ad:12:34:56"),
+            network("Guest Wifi", "00:11:22:33:44:55"),
+        ]);
+    }
+
+    #[test]
+    fn parse_iw_output() {
+        let output = "BSS 8c:3b:ad:12:34:56(on wlp2s0) -- associated
+\tfreq: 5180
+\tsignal: -54.00 dBm
+\tSSID: R56
+BSS 00:11:22:33:44:55(on wlp2s0)
+\tSSID: Guest Wifi
+";
+        assert_eq!(parse_iw(output), vec![
+            network("R56", "8c:3b:ad:12:34:56"),
+            network("Guest Wifi", "00:11:22:33:44:55"),
+        ]);
+    }
+
+    #[test]
+    fn parse_nmcli_output() {
+        let output = "R56:8C\\:3B\\:AD\\:12\\:34\\:56\nNo\\:Colons:00\\:11\\:22\\:33\\:44\\:55\n";
+        assert_eq!(parse_nmcli(output), vec![
+            network("R56", "8c:3b:ad:12:34:56"),
+            network("No:Colons", "00:11:22:33:44:55"),
+        ]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_with_fake_nmcli() {
+        use std::env;
+        use std::fs::{self, File};
+        use std::io::Write;
+        use std::os::unix::fs::PermissionsExt;
+        use std::process;
+
+        use tokio_core::reactor::Core;
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let dir = env::temp_dir().join(format!("runtext-nmcli-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let nmcli = dir.join("nmcli");
+        let mut script = File::create(&nmcli).unwrap();
+        script.write_all(b"#!/bin/sh\necho 'R56:8C\\:3B\\:AD\\:12\\:34\\:56'\n").unwrap();
+        drop(script);
+        fs::set_permissions(&nmcli, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let backend = Backend::Nmcli(nmcli);
+        let trigger = WifiScanTrigger::new(backend.clone(), vec![], vec!["8C:3B:AD:12:34:56".to_owned()]);
+        assert!(trigger.matches(&core.run(backend.scan(&handle)).unwrap()));
+
+        let trigger = WifiScanTrigger::new(backend.clone(), vec!["R56".to_owned()], vec!["00:11:22:33:44:55".to_owned()]);
+        assert!(!trigger.matches(&core.run(backend.scan(&handle)).unwrap()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
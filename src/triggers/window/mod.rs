@@ -0,0 +1,206 @@
+use std::io;
+use std::path::PathBuf;
+
+use futures::future;
+use futures::prelude::*;
+use regex::Regex;
+use serde_yaml::{self, Value};
+use tokio_core::reactor::Handle;
+
+use triggers::{changes, Activity, Trigger};
+
+mod sway;
+mod x11;
+
+pub const TRIGGER_NAME: &'static str = "focused_window";
+
+/// An evidence source that is active while the focused window matches
+/// a pattern, e.g. while a slide deck is shown fullscreen.
+///
+/// On X11 the `_NET_ACTIVE_WINDOW` property of the root window is watched
+/// using `xprop`, on sway and i3 the window events of their IPC socket are
+/// subscribed to.
+#[derive(Clone, Debug)]
+pub struct WindowTrigger {
+    backend: Backend,
+    matcher: WindowMatcher,
+}
+
+/// The ways of finding out about the focused window.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Backend {
+    /// Uses the sway / i3 IPC socket at the given path.
+    Sway(PathBuf),
+
+    /// Uses the given `xprop` binary.
+    X11(PathBuf),
+}
+
+/// The properties of a window.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Window {
+    /// The window class or, for native wayland windows, the application ID.
+    pub class: String,
+    pub title: String,
+    pub fullscreen: bool,
+}
+
+/// Conditions a window has to satisfy.
+#[derive(Clone, Debug)]
+pub struct WindowMatcher {
+    class: Option<Regex>,
+    title: Option<Regex>,
+    fullscreen: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowConfig {
+    class: Option<String>,
+    title: Option<String>,
+    fullscreen: Option<bool>,
+    backend: Option<BackendName>,
+    socket: Option<String>,
+    xprop: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BackendName {
+    Sway,
+    X11,
+}
+
+impl WindowTrigger {
+    pub fn new(backend: Backend, matcher: WindowMatcher) -> Self {
+        WindowTrigger { backend, matcher }
+    }
+
+    pub fn from_config(cfg: &Value) -> io::Result<Self> {
+        if let Value::String(ref class) = *cfg {
+            let matcher = WindowMatcher::new(Some(class), None, None)?;
+            return Ok(Self::new(Self::default_backend(), matcher));
+        }
+
+        let cfg: WindowConfig = serde_yaml::from_value(cfg.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid focused_window configuration: {}", e)))?;
+        let matcher = WindowMatcher::new(
+            cfg.class.as_ref().map(String::as_str),
+            cfg.title.as_ref().map(String::as_str),
+            cfg.fullscreen,
+        )?;
+
+        let xprop = || PathBuf::from(cfg.xprop.clone().unwrap_or("xprop".to_owned()));
+        let backend = match (cfg.backend, cfg.socket.clone()) {
+            (Some(BackendName::X11), _) => Backend::X11(xprop()),
+            (_, Some(socket)) => Backend::Sway(PathBuf::from(socket)),
+            (Some(BackendName::Sway), None) => Backend::Sway(sway::socket_path().ok_or(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Could not find the sway / i3 socket, neither SWAYSOCK nor I3SOCK are set.",
+            ))?),
+            (None, None) if cfg.xprop.is_some() => Backend::X11(xprop()),
+            (None, None) => Self::default_backend(),
+        };
+
+        Ok(Self::new(backend, matcher))
+    }
+
+    /// Uses sway / i3 if running inside of them, X11 otherwise.
+    fn default_backend() -> Backend {
+        sway::socket_path()
+            .map(Backend::Sway)
+            .unwrap_or(Backend::X11(PathBuf::from("xprop")))
+    }
+
+    fn windows(&self, handle: &Handle) -> io::Result<Box<Stream<Item = Window, Error = io::Error>>> {
+        match self.backend {
+            Backend::Sway(ref socket) => sway::windows(socket, handle),
+            Backend::X11(ref xprop) => Ok(Box::new(x11::X11Windows::new(xprop.clone(), handle)?)),
+        }
+    }
+}
+
+impl Trigger for WindowTrigger {
+    fn listen(&mut self, handle: Handle) -> Box<Stream<Item = Activity, Error = io::Error>> {
+        let matcher = self.matcher.clone();
+
+        match self.windows(&handle) {
+            Ok(windows) => Box::new(changes(windows.map(move |w| matcher.matches(&w)))),
+            Err(err) => Box::new(future::err(err).into_stream()),
+        }
+    }
+}
+
+impl WindowMatcher {
+    /// Creates a new matcher from regular expressions for the window
+    /// class and title.
+    pub fn new(class: Option<&str>, title: Option<&str>, fullscreen: Option<bool>) -> io::Result<Self> {
+        if class.is_none() && title.is_none() && fullscreen.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Missing window class, title or fullscreen condition.",
+            ));
+        }
+
+        let regex = |pattern: &str| Regex::new(pattern)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid window pattern: {}", e)));
+
+        Ok(WindowMatcher {
+            class: class.map(&regex).map_or(Ok(None), |r| r.map(Some))?,
+            title: title.map(&regex).map_or(Ok(None), |r| r.map(Some))?,
+            fullscreen,
+        })
+    }
+
+    pub fn matches(&self, window: &Window) -> bool {
+        self.class.as_ref().map_or(true, |r| r.is_match(&window.class)) &&
+            self.title.as_ref().map_or(true, |r| r.is_match(&window.title)) &&
+            self.fullscreen.map_or(true, |f| f == window.fullscreen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_cfg() {
+        let cfg = serde_yaml::from_str("{ class: impress, fullscreen: true, backend: x11 }").unwrap();
+        let trigger = WindowTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.backend, Backend::X11(PathBuf::from("xprop")));
+
+        let cfg = serde_yaml::from_str("{ title: Slides, socket: /run/sway.sock }").unwrap();
+        let trigger = WindowTrigger::from_config(&cfg).unwrap();
+        assert_eq!(trigger.backend, Backend::Sway(PathBuf::from("/run/sway.sock")));
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail_missing_condition() {
+        let cfg = serde_yaml::from_str("{ backend: x11 }").unwrap();
+        WindowTrigger::from_config(&cfg).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn load_cfg_fail_invalid_pattern() {
+        WindowTrigger::from_config(&Value::String("(impress".to_owned())).unwrap();
+    }
+
+    #[test]
+    fn match_windows() {
+        let matcher = WindowMatcher::new(Some("^libreoffice-impress$"), None, Some(true)).unwrap();
+        let mut window = Window {
+            class: "libreoffice-impress".to_owned(),
+            title: "Slides.odp".to_owned(),
+            fullscreen: true,
+        };
+        assert!(matcher.matches(&window));
+
+        window.fullscreen = false;
+        assert!(!matcher.matches(&window));
+
+        let matcher = WindowMatcher::new(None, Some("(?i)slides"), None).unwrap();
+        assert!(matcher.matches(&window));
+        assert!(!matcher.matches(&Window::default()));
+    }
+}
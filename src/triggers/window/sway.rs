@@ -0,0 +1,244 @@
+//! Finding the focused window on sway and i3 through their IPC socket.
+//!
+//! See https://i3wm.org/docs/ipc.html for the protocol.
+
+use std::env;
+use std::io;
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, NativeEndian};
+use futures::prelude::*;
+use futures::stream;
+use serde_json;
+use tokio_core::reactor::Handle;
+use tokio_io::io::{read_exact, write_all};
+use tokio_uds::UnixStream;
+
+use super::Window;
+
+const MAGIC: &'static [u8] = b"i3-ipc";
+const HEADER_LEN: usize = 14;
+
+const GET_TREE: u32 = 4;
+const SUBSCRIBE: u32 = 2;
+const WINDOW_EVENT: u32 = 0x8000_0003;
+
+/// A node of the layout tree, either a window or one of its containers.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Node {
+    #[serde(rename = "type")]
+    kind: String,
+    name: Option<String>,
+    app_id: Option<String>,
+    window_properties: Option<WindowProperties>,
+    focused: bool,
+    fullscreen_mode: u32,
+    nodes: Vec<Node>,
+    floating_nodes: Vec<Node>,
+}
+
+/// The X11 properties of a window.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct WindowProperties {
+    class: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowEvent {
+    change: String,
+    container: Node,
+}
+
+/// Gets the path to the IPC socket of the running sway or i3 instance.
+pub fn socket_path() -> Option<PathBuf> {
+    env::var_os("SWAYSOCK")
+        .or_else(|| env::var_os("I3SOCK"))
+        .map(PathBuf::from)
+}
+
+/// Subscribes to the window events of the sway / i3 instance listening
+/// on the given socket.
+///
+/// The first item is the window focused while subscribing.
+pub fn windows(socket: &Path, handle: &Handle) -> io::Result<Box<Stream<Item = Window, Error = io::Error>>> {
+    let conn = StdUnixStream::connect(socket)?;
+    let conn = UnixStream::from_std(conn, handle.new_tokio_handle())?;
+
+    let mut request = message(SUBSCRIBE, br#"["window"]"#);
+    request.extend(message(GET_TREE, b""));
+
+    let stream = write_all(conn, request)
+        .map(|(conn, _)| stream::unfold(conn, |conn| Some(read_message(conn))))
+        .flatten_stream()
+        .and_then(|(kind, payload)| parse_message(kind, &payload))
+        .filter_map(|window| window);
+
+    Ok(Box::new(stream))
+}
+
+/// Encodes an IPC message.
+fn message(kind: u32, payload: &[u8]) -> Vec<u8> {
+    let mut msg = vec![0; HEADER_LEN];
+    msg[..MAGIC.len()].copy_from_slice(MAGIC);
+    NativeEndian::write_u32(&mut msg[6..10], payload.len() as u32);
+    NativeEndian::write_u32(&mut msg[10..14], kind);
+    msg.extend_from_slice(payload);
+
+    msg
+}
+
+/// Reads the next IPC message, returning its type and payload.
+fn read_message(conn: UnixStream) -> Box<Future<Item = ((u32, Vec<u8>), UnixStream), Error = io::Error>> {
+    let fut = read_exact(conn, [0; HEADER_LEN])
+        .and_then(|(conn, header)| {
+            if &header[..MAGIC.len()] != MAGIC {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid IPC message header."));
+            }
+
+            let len = NativeEndian::read_u32(&header[6..10]) as usize;
+            let kind = NativeEndian::read_u32(&header[10..14]);
+            Ok((conn, kind, len))
+        })
+        .and_then(|(conn, kind, len)| {
+            read_exact(conn, vec![0; len])
+                .map(move |(conn, payload)| ((kind, payload), conn))
+        });
+
+    Box::new(fut)
+}
+
+/// Parses an IPC message into the focused window, if it tells
+/// anything about it.
+fn parse_message(kind: u32, payload: &[u8]) -> io::Result<Option<Window>> {
+    let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid IPC message: {}", e));
+
+    match kind {
+        GET_TREE => {
+            let tree: Node = serde_json::from_slice(payload).map_err(invalid)?;
+            Ok(Some(tree.find_focused().map(Node::to_window).unwrap_or_default()))
+        },
+        WINDOW_EVENT => {
+            let event: WindowEvent = serde_json::from_slice(payload).map_err(invalid)?;
+            match event.change.as_str() {
+                "focus" => Ok(Some(event.container.to_window())),
+                "title" | "fullscreen_mode" if event.container.focused => Ok(Some(event.container.to_window())),
+                "close" if event.container.focused => Ok(Some(Window::default())),
+                _ => Ok(None),
+            }
+        },
+        _ => Ok(None),
+    }
+}
+
+impl Node {
+    fn find_focused(&self) -> Option<&Node> {
+        if self.focused {
+            return Some(self);
+        }
+
+        self.nodes.iter()
+            .chain(self.floating_nodes.iter())
+            .filter_map(Node::find_focused)
+            .next()
+    }
+
+    fn to_window(&self) -> Window {
+        // Focused workspaces and outputs are no windows.
+        if self.kind != "con" && self.kind != "floating_con" {
+            return Window::default();
+        }
+
+        let class = self.app_id.clone()
+            .or_else(|| self.window_properties.as_ref().and_then(|p| p.class.clone()))
+            .unwrap_or_default();
+
+        Window {
+            class,
+            title: self.name.clone().unwrap_or_default(),
+            fullscreen: self.fullscreen_mode != 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::process;
+    use std::thread;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    const TREE: &'static str = r#"{
+        "type": "root", "name": "root", "focused": false,
+        "nodes": [{
+            "type": "workspace", "name": "1", "focused": false,
+            "nodes": [{ "type": "con", "name": "vim", "app_id": "foot", "focused": false }],
+            "floating_nodes": [{
+                "type": "floating_con", "name": "Slides", "focused": true, "fullscreen_mode": 1,
+                "window_properties": { "class": "libreoffice-impress" }
+            }]
+        }]
+    }"#;
+
+    #[test]
+    fn parse_tree() {
+        let window = parse_message(GET_TREE, TREE.as_bytes()).unwrap();
+        assert_eq!(window, Some(Window {
+            class: "libreoffice-impress".to_owned(),
+            title: "Slides".to_owned(),
+            fullscreen: true,
+        }));
+    }
+
+    #[test]
+    fn parse_events() {
+        let event = br#"{ "change": "title", "container": { "type": "con", "name": "vim", "focused": false } }"#;
+        assert_eq!(parse_message(WINDOW_EVENT, event).unwrap(), None);
+
+        let event = br#"{ "change": "close", "container": { "type": "con", "name": "vim", "focused": true } }"#;
+        assert_eq!(parse_message(WINDOW_EVENT, event).unwrap(), Some(Window::default()));
+    }
+
+    #[test]
+    fn subscribes_to_windows() {
+        let path = env::temp_dir().join(format!("runtext-sway-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+
+            let mut request = vec![0; 2 * HEADER_LEN + br#"["window"]"#.len()];
+            conn.read_exact(&mut request).unwrap();
+            assert_eq!(NativeEndian::read_u32(&request[10..14]), SUBSCRIBE);
+
+            conn.write_all(&message(SUBSCRIBE, br#"{ "success": true }"#)).unwrap();
+            conn.write_all(&message(GET_TREE, TREE.as_bytes())).unwrap();
+            conn.write_all(&message(
+                WINDOW_EVENT,
+                br#"{ "change": "focus", "container": { "type": "con", "name": "vim", "app_id": "foot", "focused": true } }"#,
+            )).unwrap();
+        });
+
+        let mut core = Core::new().unwrap();
+        let stream = windows(&path, &core.handle()).unwrap();
+        let windows = core.run(stream.take(2).collect()).unwrap();
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(windows.len(), 2);
+        assert!(windows[0].fullscreen);
+        assert_eq!(windows[1], Window {
+            class: "foot".to_owned(),
+            title: "vim".to_owned(),
+            fullscreen: false,
+        });
+    }
+}
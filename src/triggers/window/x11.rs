@@ -0,0 +1,256 @@
+//! Finding the focused window on X11 by spying on window properties
+//! with `xprop`.
+
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use futures::prelude::*;
+use tokio_core::reactor::Handle;
+
+use super::Window;
+use triggers::lines::ChildLines;
+
+/// The properties of the focused window that are watched for changes.
+const WINDOW_PROPERTIES: &'static [&'static str] = &["WM_CLASS", "_NET_WM_NAME", "_NET_WM_STATE"];
+
+/// A stream of the properties of the focused window.
+///
+/// This watches the `_NET_ACTIVE_WINDOW` property of the root window and
+/// the properties of whatever window it points to. A newly focused window
+/// is only yielded once all of its properties are known.
+#[derive(Debug)]
+pub struct X11Windows {
+    xprop: PathBuf,
+    handle: Handle,
+    root: ChildLines,
+    focused: Option<ChildLines>,
+    window: Window,
+
+    /// The number of properties of the focused window not read yet.
+    missing: usize,
+}
+
+impl X11Windows {
+    pub fn new(xprop: PathBuf, handle: &Handle) -> io::Result<Self> {
+        let root = ChildLines::spawn(
+            Command::new(&xprop).args(&["-spy", "-root", "_NET_ACTIVE_WINDOW"]),
+            handle,
+        )?;
+
+        Ok(X11Windows {
+            xprop,
+            handle: handle.clone(),
+            root,
+            focused: None,
+            window: Window::default(),
+            missing: 0,
+        })
+    }
+
+    /// Starts watching the properties of the window with the given ID.
+    fn focus(&mut self, id: u64) -> io::Result<()> {
+        self.window = Window::default();
+        self.focused = None;
+        self.missing = 0;
+
+        if id != 0 {
+            let spy = ChildLines::spawn(
+                Command::new(&self.xprop)
+                    .args(&["-spy", "-id", &format!("0x{:x}", id)])
+                    .args(WINDOW_PROPERTIES),
+                &self.handle,
+            )?;
+            self.focused = Some(spy);
+            self.missing = WINDOW_PROPERTIES.len();
+        }
+
+        Ok(())
+    }
+}
+
+impl Stream for X11Windows {
+    type Item = Window;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.root.poll()? {
+                Async::Ready(Some(line)) => {
+                    if let Some(id) = parse_active_window(&line) {
+                        self.focus(id)?;
+
+                        // Without a focused window there's nothing to wait for.
+                        if self.focused.is_none() {
+                            return Ok(Async::Ready(Some(self.window.clone())));
+                        }
+                    }
+
+                    continue;
+                },
+                Async::Ready(None) => return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "xprop stopped watching the active window.",
+                )),
+                Async::NotReady => {},
+            }
+
+            let line = match self.focused {
+                Some(ref mut spy) => match spy.poll()? {
+                    Async::Ready(Some(line)) => Some(line),
+                    Async::Ready(None) => None,
+                    Async::NotReady => return Ok(Async::NotReady),
+                },
+                None => return Ok(Async::NotReady),
+            };
+
+            match line {
+                Some(line) => if update_window(&mut self.window, &line) {
+                    // xprop prints every property once when it starts, the
+                    // window is incomplete until then.
+                    self.missing = self.missing.saturating_sub(1);
+                    if self.missing == 0 {
+                        return Ok(Async::Ready(Some(self.window.clone())));
+                    }
+                },
+                // The window was closed, wait for the next one to be focused.
+                None => self.focused = None,
+            }
+        }
+    }
+}
+
+/// Parses the window ID out of a line like
+/// `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x1e00007`.
+fn parse_active_window(line: &str) -> Option<u64> {
+    if !line.starts_with("_NET_ACTIVE_WINDOW") {
+        return None;
+    }
+
+    let id = line.rsplit('#').next()?
+        .split(',')
+        .next()?
+        .trim();
+    if !id.starts_with("0x") {
+        return None;
+    }
+
+    u64::from_str_radix(&id[2..], 16).ok()
+}
+
+/// Updates the window from a line of `xprop`'s output.
+///
+/// Returns whether the line contained a known window property.
+fn update_window(window: &mut Window, line: &str) -> bool {
+    let (name, value) = match line.find(|c| c == '=' || c == ':') {
+        Some(idx) => (&line[..idx], line[idx + 1..].trim()),
+        None => return false,
+    };
+    let name = name.split('(').next().unwrap_or("").trim();
+
+    match name {
+        // WM_CLASS contains the instance and the class name, we want the latter.
+        "WM_CLASS" => window.class = quoted_strings(value).pop().unwrap_or_default(),
+        "_NET_WM_NAME" => window.title = quoted_strings(value).pop().unwrap_or_default(),
+        "_NET_WM_STATE" => window.fullscreen = value.split(',').any(|s| s.trim() == "_NET_WM_STATE_FULLSCREEN"),
+        _ => return false,
+    }
+
+    true
+}
+
+/// Extracts the quoted strings out of a property value
+/// like `"libreoffice", "libreoffice-impress"`.
+fn quoted_strings(value: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut current = None;
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, current.is_some()) {
+            ('"', false) => current = Some(String::new()),
+            ('"', true) => strings.extend(current.take()),
+            ('\\', true) => if let (Some(next), Some(ref mut s)) = (chars.next(), current.as_mut()) {
+                s.push(next);
+            },
+            (c, true) => current.as_mut().unwrap().push(c),
+            _ => {},
+        }
+    }
+
+    strings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn parse_active_windows() {
+        assert_eq!(parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x1e00007"), Some(0x1e00007));
+        assert_eq!(parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0, 0x0"), Some(0));
+        assert_eq!(parse_active_window("_NET_ACTIVE_WINDOW:  not found."), None);
+    }
+
+    #[test]
+    fn update_windows() {
+        let mut window = Window::default();
+
+        assert!(update_window(&mut window, r#"WM_CLASS(STRING) = "libreoffice", "libreoffice-impress""#));
+        assert!(update_window(&mut window, r#"_NET_WM_NAME(UTF8_STRING) = "Talk \"final\".odp""#));
+        assert!(update_window(&mut window, "_NET_WM_STATE(ATOM) = _NET_WM_STATE_FOCUSED, _NET_WM_STATE_FULLSCREEN"));
+        assert!(!update_window(&mut window, "WM_HINTS(WM_HINTS):"));
+        assert_eq!(window, Window {
+            class: "libreoffice-impress".to_owned(),
+            title: "Talk \"final\".odp".to_owned(),
+            fullscreen: true,
+        });
+
+        assert!(update_window(&mut window, "_NET_WM_STATE:  not found."));
+        assert!(!window.fullscreen);
+    }
+
+    #[test]
+    fn follows_focus() {
+        let dir = env::temp_dir().join(format!("runtext-xprop-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let xprop = dir.join("xprop");
+        let mut script = File::create(&xprop).unwrap();
+        script.write_all(br#"#!/bin/sh
+case "$2" in
+    -root)
+        echo '_NET_ACTIVE_WINDOW(WINDOW): window id # 0x2a'
+        sleep 0.2
+        echo '_NET_ACTIVE_WINDOW(WINDOW): window id # 0x2b' ;;
+    -id)
+        echo 'WM_CLASS(STRING) = "term", "XTerm"'
+        echo "_NET_WM_NAME(UTF8_STRING) = \"shell $3\""
+        echo '_NET_WM_STATE(ATOM) = ' ;;
+esac
+exec sleep 10
+"#).unwrap();
+        drop(script);
+        fs::set_permissions(&xprop, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut core = Core::new().unwrap();
+        let windows = X11Windows::new(xprop, &core.handle()).unwrap();
+        let windows = core.run(windows.take(2).collect()).unwrap();
+
+        // Switching between windows doesn't go through an empty one.
+        let window = |title: &str| Window {
+            class: "XTerm".to_owned(),
+            title: title.to_owned(),
+            fullscreen: false,
+        };
+        assert_eq!(windows, vec![window("shell 0x2a"), window("shell 0x2b")]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}